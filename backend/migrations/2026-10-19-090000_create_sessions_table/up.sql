@@ -0,0 +1,5 @@
+-- SQL defining the sessions of the logged-in users, identified by a random token
+CREATE TABLE sessions (
+  token UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+);
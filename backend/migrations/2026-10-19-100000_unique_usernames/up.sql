@@ -0,0 +1,39 @@
+-- Users created concurrently before the introduction of the unique index may
+-- share the same username: we merge them into the oldest account.
+CREATE TEMPORARY TABLE duplicated_users AS
+SELECT
+  id,
+  MIN(id) OVER (PARTITION BY LOWER(username)) AS canonical_id
+FROM
+  users;
+
+UPDATE
+  comments
+SET
+  user_id = duplicated_users.canonical_id
+FROM
+  duplicated_users
+WHERE
+  comments.user_id = duplicated_users.id
+  AND duplicated_users.id <> duplicated_users.canonical_id;
+
+UPDATE
+  sessions
+SET
+  user_id = duplicated_users.canonical_id
+FROM
+  duplicated_users
+WHERE
+  sessions.user_id = duplicated_users.id
+  AND duplicated_users.id <> duplicated_users.canonical_id;
+
+DELETE FROM
+  users USING duplicated_users
+WHERE
+  users.id = duplicated_users.id
+  AND duplicated_users.id <> duplicated_users.canonical_id;
+
+DROP TABLE duplicated_users;
+
+-- Usernames are unique regardless of their case
+CREATE UNIQUE INDEX users_username_unique ON users (LOWER(username));
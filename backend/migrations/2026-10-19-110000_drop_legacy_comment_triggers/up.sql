@@ -0,0 +1,5 @@
+-- The notifications are sent by the generated `notify_comments()` instead.
+DROP TRIGGER comments_insert ON comments;
+DROP TRIGGER comments_update ON comments;
+DROP TRIGGER comments_delete ON comments;
+DROP FUNCTION notify_comment();
@@ -0,0 +1,4 @@
+-- Generated by `backend generate-triggers`, do not edit.
+
+DROP TRIGGER IF EXISTS comments_notify ON comments;
+DROP FUNCTION IF EXISTS notify_comments();
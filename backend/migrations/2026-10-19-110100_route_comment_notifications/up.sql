@@ -0,0 +1,26 @@
+-- Generated by `backend generate-triggers`, do not edit.
+
+CREATE OR REPLACE FUNCTION notify_comments() RETURNS TRIGGER AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  payload := json_build_object(
+      'action_type', TG_OP,
+      'old', CASE WHEN TG_OP = 'INSERT' THEN NULL ELSE json_build_object('id', OLD.id, 'user_id', OLD.user_id, 'body', OLD.body) END,
+      'new', CASE WHEN TG_OP = 'DELETE' THEN NULL ELSE json_build_object('id', NEW.id, 'user_id', NEW.user_id, 'body', NEW.body) END
+    )::text;
+  PERFORM pg_notify('comments', payload);
+  IF TG_OP <> 'DELETE' THEN
+    PERFORM pg_notify(CONCAT('comments_', NEW.user_id::text), payload);
+  END IF;
+  IF TG_OP = 'DELETE'
+    OR (TG_OP = 'UPDATE' AND CONCAT('comments_', OLD.user_id::text) IS DISTINCT FROM CONCAT('comments_', NEW.user_id::text)) THEN
+    PERFORM pg_notify(CONCAT('comments_', OLD.user_id::text), payload);
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS comments_notify ON comments;
+CREATE TRIGGER comments_notify AFTER INSERT OR UPDATE OR DELETE ON comments
+  FOR EACH ROW EXECUTE PROCEDURE notify_comments();
@@ -1,37 +1,75 @@
-use std::fmt::Debug;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use channel_derive::Channel;
+use commons::comments::Comment;
 use commons::users::User;
+use futures_channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures_util::StreamExt;
 use serde::Deserialize;
 
 use serde::de::DeserializeOwned;
+use serde_json::Value;
 use sqlx::error::Error;
 use sqlx::postgres::PgListener;
 use sqlx::Pool;
 use sqlx::Postgres;
+use tracing::Instrument;
 
-#[derive(Deserialize, Debug)]
+use crate::triggers::ChannelTrigger;
+
+#[derive(Deserialize, Debug, Clone)]
+// The variants match the `TG_OP` values sent by the triggers.
+#[allow(clippy::upper_case_acronyms)]
 pub enum ActionType {
     INSERT,
     UPDATE,
     DELETE,
 }
 
-pub trait Channel: ToString {
+/// Channel notified by the database triggers.
+///
+/// The channels are declared with `#[derive(Channel)]`, which also implements
+/// their `Display` as the name of the channel.
+pub trait Channel: Display {
+    /// Name shared by all the channels of this kind, used to label the metrics.
+    const NAME: &'static str;
+    /// Table whose changes are notified on the channel.
+    const TABLE: &'static str;
+    /// Column identifying the rows of the table, sent instead of the rows
+    /// when they are too large to be notified.
+    const KEY: &'static str;
+    /// Parts of the name of the channel, as built by the triggers from the
+    /// changed row.
+    const ROUTE: &'static [Route];
+    /// Row sent by the triggers, before and after the change.
     type Payload: DeserializeOwned + Debug;
 }
 
-pub struct CommentsChannel;
-
-impl ToString for CommentsChannel {
-    fn to_string(&self) -> String {
-        "comments".to_string()
-    }
+/// Part of the name of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Literal(&'static str),
+    /// Value of a column of the changed row.
+    Column(&'static str),
 }
 
-impl Channel for CommentsChannel {
-    type Payload = CommentsPayload;
-}
+#[derive(Channel)]
+#[channel(name = "comments", payload = Comment, table = "comments")]
+pub struct CommentsChannel;
 
+#[derive(Channel)]
+#[channel(
+    name = "comments_{user.id}",
+    payload = Comment,
+    table = "comments",
+    columns = ["user_id"]
+)]
 pub struct CommentsUserChannel {
     pub user: User,
 }
@@ -42,56 +80,628 @@ impl CommentsUserChannel {
     }
 }
 
-impl ToString for CommentsUserChannel {
-    fn to_string(&self) -> String {
-        format!("comments_{}", self.user.id)
+/// Notification sent by a trigger, with the row it was fired for.
+#[derive(Debug, Clone)]
+pub struct Notification<T> {
+    pub action_type: ActionType,
+    /// Row before the change, on UPDATE and DELETE.
+    pub old: Option<Row<T>>,
+    /// Row after the change, on INSERT and UPDATE.
+    pub new: Option<Row<T>>,
+    /// Position of the event with the outbox source.
+    position: Option<(i64, i64)>,
+}
+
+/// Row of a notification, unless it was too large to be notified.
+#[derive(Debug, Clone)]
+pub enum Row<T> {
+    /// The row as notified, or as fetched once notified when it was too large.
+    Full(T),
+    /// Key of a row too large to be notified, which is not in the table
+    /// anymore, such as the old row of an UPDATE or DELETE.
+    Truncated(Value),
+}
+
+impl<T> Row<T> {
+    fn try_map<U, E>(self, f: impl FnOnce(T) -> Result<U, E>) -> Result<Row<U>, E> {
+        match self {
+            Row::Full(row) => f(row).map(Row::Full),
+            Row::Truncated(key) => Ok(Row::Truncated(key)),
+        }
     }
 }
 
-impl Channel for CommentsUserChannel {
-    type Payload = CommentsPayload;
+impl<T> Notification<T> {
+    /// Returns the row after the change, or before it when it was deleted.
+    pub fn into_record(self) -> Option<Row<T>> {
+        self.new.or(self.old)
+    }
+
+    fn try_map<U, E>(self, mut f: impl FnMut(T) -> Result<U, E>) -> Result<Notification<U>, E> {
+        Ok(Notification {
+            action_type: self.action_type,
+            old: self.old.map(|row| row.try_map(&mut f)).transpose()?,
+            new: self.new.map(|row| row.try_map(&mut f)).transpose()?,
+            position: self.position,
+        })
+    }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct CommentsPayload {
-    pub action_type: ActionType,
-    pub id: i32,
-    pub user_id: i32,
-    pub body: String,
+/// Payload sent by the triggers, where the rows too large to be notified are
+/// replaced by objects with only their keys, marked as truncated.
+#[derive(Deserialize)]
+struct Payload {
+    action_type: ActionType,
+    old: Option<Value>,
+    new: Option<Value>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+/// Replaces the keys of a truncated payload with the rows they identify, as
+/// long as they are in the table, keeping the keys of the others.
+async fn fetch_rows(
+    pool: &Pool<Postgres>,
+    fetch_query: &str,
+    key: &str,
+    payload: Payload,
+) -> Result<Notification<Value>, Error> {
+    let key_of = |mut row: Value| row.get_mut(key).map(Value::take).unwrap_or_default();
+    let new = match payload.new {
+        Some(new) => {
+            let row: Option<String> = sqlx::query_scalar(fetch_query)
+                .bind(new.to_string())
+                .fetch_optional(pool)
+                .await?;
+            let row = row
+                .map(|row| serde_json::from_str(&row))
+                .transpose()
+                .map_err(|err| Error::Decode(err.into()))?;
+            if row.is_none() {
+                tracing::warn!(
+                    "The row of a truncated {:?} notification is gone",
+                    payload.action_type
+                );
+            }
+            Some(row.map_or_else(|| Row::Truncated(key_of(new)), Row::Full))
+        }
+        None => None,
+    };
+    Ok(Notification {
+        action_type: payload.action_type,
+        old: payload.old.map(key_of).map(Row::Truncated),
+        new,
+        position: None,
+    })
+}
+
+/// Where the listeners read the changes of the channels from.
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    /// The notifications sent by the triggers, lost while not listening.
+    Notify,
+    /// The `events` table written by the triggers generated with
+    /// `generate-triggers --outbox`, read when the triggers notify the channel
+    /// and at least every `poll_interval`.
+    Outbox { poll_interval: Duration },
+}
+
+/// Channel notified to wake the shared listener up, such as when a channel is
+/// subscribed to or the outbox is to be polled.
+const WAKE_CHANNEL: &str = "ayw_listeners";
+
+/// Delay before listening again after an error.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Maximum number of events read at once from the outbox.
+const TAIL_LIMIT: i64 = 1000;
+
+/// Events following a position, among those of the finished transactions.
+///
+/// The events are read in the order of their transactions, as all those
+/// before the oldest running one are finished: the events of a transaction
+/// committing later cannot be skipped, whatever their ids.
+const TAIL_EVENTS: &str = "SELECT txid, id, channel, payload FROM events \
+    WHERE (txid, id) > ($1, $2) \
+    AND txid < pg_snapshot_xmin(pg_current_snapshot())::text::bigint \
+    ORDER BY txid, id LIMIT $3";
+
+/// Events of a channel between two positions, caught up with by the resuming
+/// clients.
+const CATCH_UP_EVENTS: &str = "SELECT txid, id, payload FROM events \
+    WHERE channel = $1 AND (txid, id) > ($2, $3) AND (txid, id) <= ($4, $5) \
+    ORDER BY txid, id";
+
+/// How the notifications of a channel are read, shared by its subscribers.
+struct Subscribers {
+    /// Name shared by all the channels of this kind, used to label the metrics.
+    name: &'static str,
+    key: &'static str,
+    /// Query fetching the rows of the truncated notifications.
+    fetch_query: String,
+    senders: Vec<UnboundedSender<Notification<Value>>>,
+}
+
+#[derive(Default)]
+struct ListenersState {
+    channels: HashMap<String, Subscribers>,
+    /// Position of the last event sent to the subscribers, with the outbox
+    /// source.
+    position: Option<(i64, i64)>,
+}
+
+/// Listener of the channels shared by all the websockets, which would
+/// otherwise hold a connection each.
+///
+/// A single connection listens to the channels subscribed to and, with the
+/// outbox source, a single query tails the events of all of them, so that the
+/// subscribers receive them in the same order. The notifications are sent to
+/// the subscribers of their channel as long as they keep their [`Subscription`].
+#[derive(Clone)]
+pub struct Listeners {
+    pool: Pool<Postgres>,
+    source: Source,
+    state: Arc<Mutex<ListenersState>>,
+    /// Whether the connection listening to the channels is up.
+    connected: Arc<AtomicBool>,
+}
+
+/// Notifications of a channel, as sent by the [`Listeners`].
+pub struct Subscription {
+    receiver: UnboundedReceiver<Notification<Value>>,
+    /// Position of the [`Listeners`] when subscribing, the following events
+    /// being received.
+    position: Option<(i64, i64)>,
+}
+
+impl Listeners {
+    /// Creates the listeners, reading the outbox from the events of the
+    /// transactions still running.
+    pub async fn new(pool: Pool<Postgres>, source: Source) -> Result<Self, Error> {
+        let position = match source {
+            Source::Notify => None,
+            Source::Outbox { .. } => Some((oldest_running_txid(&pool).await?, 0)),
+        };
+        Ok(Self {
+            pool,
+            source,
+            state: Arc::new(Mutex::new(ListenersState {
+                channels: HashMap::new(),
+                position,
+            })),
+            connected: Arc::default(),
+        })
+    }
+
+    /// Subscribes to the notifications of the channel, listening to it unless
+    /// already listened to.
+    pub fn subscribe<Ch: Channel>(&self, channel: &Ch) -> Subscription {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut state = self.state.lock().unwrap();
+        let position = state.position;
+        let subscribers = state
+            .channels
+            .entry(channel.to_string())
+            .or_insert_with(|| Subscribers {
+                name: Ch::NAME,
+                key: Ch::KEY,
+                fetch_query: ChannelTrigger::of::<Ch>().fetch_query(),
+                senders: Vec::new(),
+            });
+        if subscribers.senders.is_empty() {
+            actix_web::rt::spawn(wake(self.pool.clone()));
+        }
+        subscribers.senders.push(sender);
+        Subscription { receiver, position }
+    }
+
+    /// Listens to the channels subscribed to for ever, listening again from
+    /// the same position after an error.
+    pub async fn run(self) {
+        if let Source::Outbox { poll_interval } = self.source {
+            actix_web::rt::spawn(poll(self.pool.clone(), poll_interval));
+        }
+        loop {
+            if let Err(err) = self.listen().await {
+                self.connected.store(false, Ordering::Relaxed);
+                tracing::error!("Error listening to the channels: {}", err);
+                actix_web::rt::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+
+    /// Returns whether the connection listening to the channels is up, as
+    /// far as the last attempt to use it tells.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    async fn listen(&self) -> Result<(), Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        let mut listened = HashSet::new();
+        loop {
+            if !self.is_connected() {
+                // Listening again reconnects the listener after it lost its
+                // connection, which it then listens to all the channels on.
+                listener.listen(WAKE_CHANNEL).await?;
+                self.connected.store(true, Ordering::Relaxed);
+            }
+            self.update_channels(&mut listener, &mut listened).await?;
+            if let Source::Outbox { .. } = self.source {
+                self.tail_events().await?;
+            }
+            // Nothing is lost on reconnection with the outbox source, which
+            // is tailed again once woken up.
+            let Some(notification) = listener.try_recv().await? else {
+                tracing::warn!("Lost the connection listening to the channels");
+                self.connected.store(false, Ordering::Relaxed);
+                continue;
+            };
+            let channel = notification.channel();
+            if matches!(self.source, Source::Notify) && channel != WAKE_CHANNEL {
+                let span = tracing::info_span!("notification", channel);
+                span.in_scope(|| {
+                    tracing::debug!(
+                        "Getting notification with payload: {:?}",
+                        notification.payload()
+                    )
+                });
+                self.dispatch(channel, notification.payload(), None)
+                    .instrument(span)
+                    .await?;
+            }
+        }
+    }
+
+    /// Listens to the channels subscribed to, and stops listening to those
+    /// without subscribers anymore.
+    async fn update_channels(
+        &self,
+        listener: &mut PgListener,
+        listened: &mut HashSet<String>,
+    ) -> Result<(), Error> {
+        let subscribed: HashSet<String> = {
+            let mut state = self.state.lock().unwrap();
+            state.channels.retain(|_, subscribers| {
+                subscribers.senders.retain(|sender| !sender.is_closed());
+                !subscribers.senders.is_empty()
+            });
+            state.channels.keys().cloned().collect()
+        };
+        for channel in subscribed.difference(listened) {
+            listener.listen(channel).await?;
+            tracing::info!("Listening to channel: {}", channel);
+        }
+        for channel in listened.difference(&subscribed) {
+            listener.unlisten(channel).await?;
+            tracing::info!("Stopped listening to channel: {}", channel);
+        }
+        *listened = subscribed;
+        Ok(())
+    }
+
+    /// Sends the events following the position to their subscribers.
+    async fn tail_events(&self) -> Result<(), Error> {
+        loop {
+            let position = self.state.lock().unwrap().position.unwrap_or_default();
+            let events: Vec<(i64, i64, String, String)> = sqlx::query_as(TAIL_EVENTS)
+                .bind(position.0)
+                .bind(position.1)
+                .bind(TAIL_LIMIT)
+                .fetch_all(&self.pool)
+                .await?;
+            let count = events.len() as i64;
+            for (txid, id, channel, payload) in events {
+                let span = tracing::info_span!("event", channel, id);
+                span.in_scope(|| tracing::debug!("Getting event with payload: {:?}", payload));
+                self.dispatch(&channel, &payload, Some((txid, id)))
+                    .instrument(span)
+                    .await?;
+            }
+            if count < TAIL_LIMIT {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Sends the notification to the subscribers of the channel, moving the
+    /// position past its event.
+    async fn dispatch(
+        &self,
+        channel: &str,
+        payload: &str,
+        event: Option<(i64, i64)>,
+    ) -> Result<(), Error> {
+        let parsing = self
+            .state
+            .lock()
+            .unwrap()
+            .channels
+            .get(channel)
+            .map(|subscribers| {
+                (
+                    subscribers.name,
+                    subscribers.key,
+                    subscribers.fetch_query.clone(),
+                )
+            });
+        let notification = match parsing {
+            Some((name, key, fetch_query)) => {
+                parse_payload(&self.pool, name, key, &fetch_query, payload).await?
+            }
+            None => None,
+        };
+        let mut state = self.state.lock().unwrap();
+        if let (Some(mut notification), Some(subscribers)) =
+            (notification, state.channels.get_mut(channel))
+        {
+            notification.position = event;
+            subscribers
+                .senders
+                .retain(|sender| sender.unbounded_send(notification.clone()).is_ok());
+        }
+        if event.is_some() {
+            state.position = event;
+        }
+        Ok(())
+    }
+
+    /// Returns the events of the channel between the positions.
+    async fn catch_up_events(
+        &self,
+        channel: &str,
+        from: (i64, i64),
+        until: (i64, i64),
+    ) -> Result<Vec<(i64, i64, String)>, Error> {
+        sqlx::query_as(CATCH_UP_EVENTS)
+            .bind(channel)
+            .bind(from.0)
+            .bind(from.1)
+            .bind(until.0)
+            .bind(until.1)
+            .fetch_all(&self.pool)
+            .await
+    }
 }
 
-impl Into<commons::comments::Comment> for CommentsPayload {
-    fn into(self) -> commons::comments::Comment {
-        commons::comments::Comment {
-            id: self.id,
-            user_id: self.user_id,
-            body: self.body,
+/// Wakes the listener up, as when a channel is to be listened to.
+async fn wake(pool: Pool<Postgres>) {
+    let woken = sqlx::query("SELECT pg_notify($1, '')")
+        .bind(WAKE_CHANNEL)
+        .execute(&pool)
+        .await;
+    if let Err(err) = woken {
+        tracing::error!("Error waking the listener up: {}", err);
+    }
+}
+
+/// Wakes the listener up every `poll_interval`, for ever, so that it reads the
+/// events it was not notified of.
+async fn poll(pool: Pool<Postgres>, poll_interval: Duration) {
+    let mut interval = actix_web::rt::time::interval(poll_interval);
+    loop {
+        interval.tick().await;
+        wake(pool.clone()).await;
+    }
+}
+
+/// Returns the id of the oldest running transaction, whose events and those
+/// of the following transactions are still to be read.
+async fn oldest_running_txid(pool: &Pool<Postgres>) -> Result<i64, Error> {
+    sqlx::query_scalar("SELECT pg_snapshot_xmin(pg_current_snapshot())::text::bigint")
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns the position of the event, if still kept.
+async fn event_position(pool: &Pool<Postgres>, id: i64) -> Result<Option<(i64, i64)>, Error> {
+    let txid: Option<i64> = sqlx::query_scalar("SELECT txid FROM events WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(txid.map(|txid| (txid, id)))
+}
+
+/// Position of a websocket in the events of the outbox, shared by the
+/// listeners of its channels.
+///
+/// A resuming client catches up with the events of each channel it subscribes
+/// to, from the event it resumes after to the position of the [`Listeners`]
+/// when it first subscribed. The events are otherwise sent in the order of
+/// the outbox, so the id of the last event sent is given back to the client,
+/// except while catching up: it is then held until all the channels caught up.
+#[derive(Clone, Default)]
+pub struct Cursor(Rc<RefCell<CursorState>>);
+
+#[derive(Default)]
+struct CursorState {
+    /// Event after which the client resumes.
+    last_event_id: Option<i64>,
+    /// Position until which the channels catch up.
+    until: Option<(i64, i64)>,
+    /// Number of channels catching up.
+    catching_up: usize,
+    /// Position of the last event sent while catching up.
+    held: Option<(i64, i64)>,
+}
+
+impl Cursor {
+    pub fn new(last_event_id: Option<i64>) -> Self {
+        Self(Rc::new(RefCell::new(CursorState {
+            last_event_id,
+            ..CursorState::default()
+        })))
+    }
+
+    /// Returns the event to resume after and the position to catch up until,
+    /// when resuming.
+    fn start_catch_up(&self, position: Option<(i64, i64)>) -> Option<(i64, (i64, i64))> {
+        let mut state = self.0.borrow_mut();
+        let last_event_id = state.last_event_id?;
+        let until = *state.until.get_or_insert(position?);
+        state.catching_up += 1;
+        Some((last_event_id, until))
+    }
+
+    /// Returns the id of the last event sent, to be given back once no
+    /// channel is catching up anymore.
+    fn end_catch_up(&self) -> Option<i64> {
+        let mut state = self.0.borrow_mut();
+        state.catching_up -= 1;
+        if state.catching_up > 0 {
+            return None;
         }
+        state.held.take().map(|(_, id)| id)
+    }
+
+    /// Returns the id of the event sent, unless held while catching up.
+    fn sent(&self, position: (i64, i64)) -> Option<i64> {
+        let mut state = self.0.borrow_mut();
+        if state.catching_up == 0 {
+            return Some(position.1);
+        }
+        state.held = state.held.max(Some(position));
+        None
     }
 }
 
+/// Calls back with the notifications of the channel for ever.
+///
+/// With the outbox source, a resuming client first catches up with the events
+/// of the channel, as described by [`Cursor`], and `give_back` is called with
+/// the ids of the events it can resume after.
 pub async fn start_listening<Ch: Channel>(
-    pool: &Pool<Postgres>,
+    listeners: &Listeners,
+    cursor: &Cursor,
     channel: Ch,
-    mut call_back: impl FnMut(Ch::Payload),
-) -> Result<(), Error> {
-    // Initiate the logger.
-    let mut listener = PgListener::connect_with(pool).await.unwrap();
-    listener
-        .listen_all(vec![channel.to_string().as_str()])
-        .await?;
-    log::info!("Listening to channel: {}", channel.to_string());
-    loop {
-        while let Some(notification) = listener.try_recv().await? {
-            log::info!(
-                "Getting notification with payload: {:?} from channel {:?}",
-                notification.payload(),
-                notification.channel()
-            );
+    mut call_back: impl FnMut(Notification<Ch::Payload>),
+    mut give_back: impl FnMut(i64),
+) {
+    let name = channel.to_string();
+    let Subscription {
+        mut receiver,
+        position,
+    } = listeners.subscribe(&channel);
+    let mut deliver = |notification: Notification<Value>| {
+        let event = notification.position;
+        match notification.try_map(serde_json::from_value) {
+            Ok(notification) => call_back(notification),
+            Err(err) => tracing::error!("Error parsing the rows of a notification: {}", err),
+        }
+        event.and_then(|event| cursor.sent(event))
+    };
+
+    if let Some((last_event_id, until)) = cursor.start_catch_up(position) {
+        tracing::info!(
+            "Catching up with channel {} after event {}",
+            name,
+            last_event_id
+        );
+        let events = loop {
+            let events = match event_position(&listeners.pool, last_event_id).await {
+                Ok(Some(from)) => listeners.catch_up_events(&name, from, until).await,
+                Ok(None) => {
+                    tracing::warn!(
+                        "Cannot resume after the event {}, not kept anymore",
+                        last_event_id
+                    );
+                    Ok(Vec::new())
+                }
+                Err(err) => Err(err),
+            };
+            match events {
+                Ok(events) => break events,
+                Err(err) => {
+                    tracing::error!("Error catching up with channel {}: {}", name, err);
+                    actix_web::rt::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        };
+        let fetch_query = ChannelTrigger::of::<Ch>().fetch_query();
+        for (txid, id, payload) in events {
+            let parsed = parse_payload(&listeners.pool, Ch::NAME, Ch::KEY, &fetch_query, &payload)
+                .instrument(tracing::info_span!("event", channel = name, id))
+                .await;
+            match parsed {
+                Ok(Some(mut notification)) => {
+                    notification.position = Some((txid, id));
+                    // The ids are held while catching up.
+                    deliver(notification);
+                }
+                Ok(None) => {}
+                Err(err) => tracing::error!("Error catching up with event {}: {}", id, err),
+            }
+        }
+        if let Some(id) = cursor.end_catch_up() {
+            give_back(id);
+        }
+    }
 
-            let notification_payload: String = notification.payload().to_owned();
+    while let Some(notification) = receiver.next().await {
+        if let Some(id) = deliver(notification) {
+            give_back(id);
+        }
+    }
+}
 
-            call_back(serde_json::from_str::<Ch::Payload>(&notification_payload).unwrap());
+/// Parses the payload of a notification or event, fetching the rows of the
+/// truncated ones.
+///
+/// A payload that cannot be parsed is logged and skipped, returning `None`,
+/// while the errors of the database are returned.
+async fn parse_payload(
+    pool: &Pool<Postgres>,
+    name: &str,
+    key: &str,
+    fetch_query: &str,
+    payload: &str,
+) -> Result<Option<Notification<Value>>, Error> {
+    crate::metrics::METRICS
+        .notifications
+        .with_label_values(&[name])
+        .inc();
+
+    let payload: Payload = match serde_json::from_str(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            tracing::error!("Error parsing notification: {}", err);
+            return Ok(None);
+        }
+    };
+    if payload.truncated {
+        return fetch_rows(pool, fetch_query, key, payload).await.map(Some);
+    }
+    Ok(Some(Notification {
+        action_type: payload.action_type,
+        old: payload.old.map(Row::Full),
+        new: payload.new.map(Row::Full),
+        position: None,
+    }))
+}
+
+/// Checks that the `events` table of the outbox source exists.
+pub async fn check_outbox(pool: &Pool<Postgres>) -> Result<bool, Error> {
+    sqlx::query_scalar("SELECT to_regclass('events') IS NOT NULL")
+        .fetch_one(pool)
+        .await
+}
+
+/// Interval between two deletions of the events past their retention.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Deletes the events older than `retention` periodically, for ever.
+pub async fn prune_events(pool: Pool<Postgres>, retention: Duration) {
+    let mut interval = actix_web::rt::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let deleted =
+            sqlx::query("DELETE FROM events WHERE created_at < now() - make_interval(secs => $1)")
+                .bind(retention.as_secs_f64())
+                .execute(&pool)
+                .await;
+        match deleted {
+            Ok(deleted) => tracing::debug!("Pruned {} events", deleted.rows_affected()),
+            Err(err) => tracing::error!("Error pruning the events: {}", err),
         }
     }
 }
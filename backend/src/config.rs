@@ -0,0 +1,651 @@
+//! Configuration of the backend server.
+//!
+//! The configuration is made of the defaults below, optionally overridden by a
+//! TOML file whose path is provided by the `CONFIG_FILE` environment variable,
+//! in turn overridden by the individual environment variables listed in
+//! [`Config::override_from_env`].
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use commons::messages::FrontendMessage;
+use serde::Deserialize;
+
+const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Host the HTTP server binds to.
+    pub host: String,
+    /// Port the HTTP server binds to.
+    pub port: u16,
+    /// Number of worker threads of the HTTP server.
+    pub workers: usize,
+    /// Maximum number of connections of the Diesel pool.
+    pub diesel_pool_size: u32,
+    /// Maximum number of connections of the SQLx pool.
+    pub sqlx_pool_size: u32,
+    /// Origins allowed to make cross-origin requests and to open websockets.
+    pub allowed_origins: Vec<String>,
+    /// Default log level, used when `RUST_LOG` is not set.
+    pub log_level: String,
+    /// Format of the log lines written to the standard output.
+    pub log_format: LogFormat,
+    /// Directory containing the frontend built by trunk.
+    pub frontend_dist: PathBuf,
+    pub websocket: WebsocketConfig,
+    pub notifications: NotificationsConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines, one per event.
+    Text,
+    /// One JSON object per event, including the fields of its spans.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the changes of the database are read from.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationMode {
+    /// The notifications sent by the triggers, lost while not listening.
+    Notify,
+    /// The `events` table written by the triggers generated with
+    /// `generate-triggers --outbox`, from which the clients can resume.
+    Outbox,
+}
+
+impl FromStr for NotificationMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "notify" => Ok(NotificationMode::Notify),
+            "outbox" => Ok(NotificationMode::Outbox),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    pub mode: NotificationMode,
+    /// Milliseconds between two reads of the `events` table in the outbox
+    /// mode, which is also read as soon as the triggers notify it.
+    pub outbox_poll_interval_ms: u64,
+    /// Seconds the events are kept in the `events` table in the outbox mode,
+    /// bounding how late the clients can resume.
+    pub outbox_retention_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebsocketConfig {
+    /// Maximum size in bytes of the frames received from the clients.
+    pub max_frame_size: usize,
+    /// Maximum size in bytes of the messages received from the clients, which
+    /// may span several continuation frames.
+    pub max_payload_size: usize,
+    /// Seconds the clients are asked to wait before reconnecting when the
+    /// server shuts down, or zero not to provide any hint.
+    pub shutdown_retry_after: u64,
+    /// Milliseconds during which the events sent to a client are coalesced
+    /// into a single batch, or zero to send them one by one.
+    pub batch_window_ms: u64,
+    /// Maximum number of events in a batch, sent as soon as it is full.
+    pub max_batch_size: usize,
+    pub rate_limits: RateLimitsConfig,
+}
+
+/// Token bucket limiting the messages of a kind.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// Number of messages that can be sent at once.
+    pub burst: u32,
+    /// Number of messages per second the bucket is refilled with.
+    pub per_second: f64,
+}
+
+impl RateLimit {
+    const fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+}
+
+/// Rate limits of the messages received from the frontends, per message kind.
+///
+/// The kinds that are not listed are not limited.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitsConfig {
+    /// Limits of each websocket session.
+    pub session: HashMap<String, RateLimit>,
+    /// Limits of each user, shared by all their sessions.
+    pub user: HashMap<String, RateLimit>,
+    /// Number of consecutive messages exceeding the limits after which the
+    /// websocket is closed.
+    pub max_violations: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 8080,
+            workers: 2,
+            diesel_pool_size: 10,
+            sqlx_pool_size: 10,
+            // Since in the development we are currently using Trunk, we need to
+            // support CROSS ORIGIN RESOURCE SHARING (CORS) for the frontend
+            // to be able to connect to the backend.
+            allowed_origins: vec!["http://localhost:3000".to_string()],
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            // The directory where trunk builds the frontend, relative to the backend crate
+            frontend_dist: PathBuf::from("../frontend/dist"),
+            websocket: WebsocketConfig::default(),
+            notifications: NotificationsConfig::default(),
+        }
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            mode: NotificationMode::Notify,
+            outbox_poll_interval_ms: 1000,
+            outbox_retention_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 64 * 1024,
+            max_payload_size: 256 * 1024,
+            shutdown_retry_after: 5,
+            batch_window_ms: 20,
+            max_batch_size: 100,
+            rate_limits: RateLimitsConfig::default(),
+        }
+    }
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        let session = [
+            ("Login", RateLimit::new(5, 0.2)),
+            ("Resume", RateLimit::new(5, 0.2)),
+            ("InsertComment", RateLimit::new(5, 1.0)),
+            ("DeleteComment", RateLimit::new(10, 2.0)),
+        ];
+        let user = [
+            ("InsertComment", RateLimit::new(10, 2.0)),
+            ("DeleteComment", RateLimit::new(20, 4.0)),
+        ];
+        Self {
+            session: session
+                .into_iter()
+                .map(|(kind, limit)| (kind.to_string(), limit))
+                .collect(),
+            user: user
+                .into_iter()
+                .map(|(kind, limit)| (kind.to_string(), limit))
+                .collect(),
+            max_violations: 20,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    File(PathBuf, std::io::Error),
+    Toml(PathBuf, toml::de::Error),
+    Env {
+        name: &'static str,
+        value: String,
+    },
+    Invalid {
+        field: &'static str,
+        reason: String,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::File(path, err) => {
+                write!(f, "cannot read the configuration file {}: {}", path.display(), err)
+            }
+            ConfigError::Toml(path, err) => {
+                write!(f, "cannot parse the configuration file {}: {}", path.display(), err)
+            }
+            ConfigError::Env { name, value } => {
+                write!(f, "the environment variable {} has an invalid value {:?}", name, value)
+            }
+            ConfigError::Invalid { field, reason } => {
+                write!(f, "the configuration field `{}` is invalid: {}", field, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads the configuration, failing on any invalid value.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var_os("CONFIG_FILE") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None => Self::default(),
+        };
+        config.override_from_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads the configuration from the provided TOML file, where all fields are optional.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::File(path.to_path_buf(), err))?;
+        toml::from_str(&content).map_err(|err| ConfigError::Toml(path.to_path_buf(), err))
+    }
+
+    /// Overrides the configuration with the environment variables that are set.
+    ///
+    /// The supported variables are `ACTIX_HOST`, `ACTIX_PORT`, `ACTIX_WORKERS`,
+    /// `DIESEL_POOL_SIZE`, `SQLX_POOL_SIZE`, `ALLOWED_ORIGINS` (comma-separated),
+    /// `LOG_LEVEL`, `LOG_FORMAT`, `FRONTEND_DIST`, `WS_MAX_FRAME_SIZE`,
+    /// `WS_MAX_PAYLOAD_SIZE`, `WS_SHUTDOWN_RETRY_AFTER`, `WS_BATCH_WINDOW_MS`,
+    /// `WS_MAX_BATCH_SIZE`, `NOTIFICATION_MODE`, `OUTBOX_POLL_INTERVAL_MS` and
+    /// `OUTBOX_RETENTION_SECS`.
+    pub fn override_from_env(&mut self) -> Result<(), ConfigError> {
+        self.override_from(|name| std::env::var(name).ok())
+    }
+
+    /// Overrides the configuration with the variables returned by `var`, as
+    /// [`Config::override_from_env`] does with the environment variables.
+    fn override_from(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        env_override(&var, "ACTIX_HOST", &mut self.host)?;
+        env_override(&var, "ACTIX_PORT", &mut self.port)?;
+        env_override(&var, "ACTIX_WORKERS", &mut self.workers)?;
+        env_override(&var, "DIESEL_POOL_SIZE", &mut self.diesel_pool_size)?;
+        env_override(&var, "SQLX_POOL_SIZE", &mut self.sqlx_pool_size)?;
+        if let Some(origins) = var("ALLOWED_ORIGINS") {
+            self.allowed_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        env_override(&var, "LOG_LEVEL", &mut self.log_level)?;
+        env_override(&var, "LOG_FORMAT", &mut self.log_format)?;
+        env_override(&var, "FRONTEND_DIST", &mut self.frontend_dist)?;
+        env_override(&var, "WS_MAX_FRAME_SIZE", &mut self.websocket.max_frame_size)?;
+        env_override(&var, "WS_MAX_PAYLOAD_SIZE", &mut self.websocket.max_payload_size)?;
+        env_override(&var, "WS_SHUTDOWN_RETRY_AFTER", &mut self.websocket.shutdown_retry_after)?;
+        env_override(&var, "WS_BATCH_WINDOW_MS", &mut self.websocket.batch_window_ms)?;
+        env_override(&var, "WS_MAX_BATCH_SIZE", &mut self.websocket.max_batch_size)?;
+        env_override(&var, "NOTIFICATION_MODE", &mut self.notifications.mode)?;
+        env_override(
+            &var,
+            "OUTBOX_POLL_INTERVAL_MS",
+            &mut self.notifications.outbox_poll_interval_ms,
+        )?;
+        env_override(
+            &var,
+            "OUTBOX_RETENTION_SECS",
+            &mut self.notifications.outbox_retention_secs,
+        )?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.host.is_empty() {
+            return Err(invalid("host", "it cannot be empty"));
+        }
+        if self.port == 0 {
+            return Err(invalid("port", "it must be between 1 and 65535"));
+        }
+        if self.workers == 0 {
+            return Err(invalid("workers", "at least one worker is needed"));
+        }
+        if self.diesel_pool_size == 0 {
+            return Err(invalid("diesel_pool_size", "at least one connection is needed"));
+        }
+        if self.sqlx_pool_size == 0 {
+            return Err(invalid("sqlx_pool_size", "at least one connection is needed"));
+        }
+        for origin in &self.allowed_origins {
+            let valid = ["http://", "https://"].iter().any(|scheme| {
+                origin
+                    .strip_prefix(scheme)
+                    .is_some_and(|host| !host.is_empty() && !host.contains('/'))
+            });
+            if !valid {
+                return Err(invalid(
+                    "allowed_origins",
+                    format!(
+                        "{:?} is not an origin such as \"https://example.com:8080\"",
+                        origin
+                    ),
+                ));
+            }
+        }
+        if !LOG_LEVELS.contains(&self.log_level.as_str()) {
+            return Err(invalid(
+                "log_level",
+                format!("{:?} is not one of {}", self.log_level, LOG_LEVELS.join(", ")),
+            ));
+        }
+        if self.websocket.max_frame_size == 0 {
+            return Err(invalid("websocket.max_frame_size", "it must be positive"));
+        }
+        if self.websocket.max_payload_size < self.websocket.max_frame_size {
+            return Err(invalid(
+                "websocket.max_payload_size",
+                "it cannot be smaller than websocket.max_frame_size",
+            ));
+        }
+        if self.websocket.max_batch_size == 0 {
+            return Err(invalid(
+                "websocket.max_batch_size",
+                "at least one event per batch is needed",
+            ));
+        }
+        self.websocket.rate_limits.validate()?;
+        if self.notifications.outbox_poll_interval_ms == 0 {
+            return Err(invalid(
+                "notifications.outbox_poll_interval_ms",
+                "it must be positive",
+            ));
+        }
+        if self.notifications.outbox_retention_secs == 0 {
+            return Err(invalid(
+                "notifications.outbox_retention_secs",
+                "it must be positive",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl RateLimitsConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (field, limits) in [
+            ("websocket.rate_limits.session", &self.session),
+            ("websocket.rate_limits.user", &self.user),
+        ] {
+            for (kind, limit) in limits {
+                if !FrontendMessage::KINDS.contains(&kind.as_str()) {
+                    return Err(invalid(
+                        field,
+                        format!(
+                            "{:?} is not one of {}",
+                            kind,
+                            FrontendMessage::KINDS.join(", ")
+                        ),
+                    ));
+                }
+                if limit.burst == 0 || !limit.per_second.is_finite() || limit.per_second <= 0.0 {
+                    return Err(invalid(
+                        field,
+                        format!("the burst and refill rate of {} must be positive", kind),
+                    ));
+                }
+            }
+        }
+        if self.max_violations == 0 {
+            return Err(invalid(
+                "websocket.rate_limits.max_violations",
+                "it must be positive",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(field: &'static str, reason: impl ToString) -> ConfigError {
+    ConfigError::Invalid {
+        field,
+        reason: reason.to_string(),
+    }
+}
+
+fn env_override<T: FromStr>(
+    var: impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    target: &mut T,
+) -> Result<(), ConfigError> {
+    if let Some(value) = var(name) {
+        *target = value
+            .parse()
+            .map_err(|_| ConfigError::Env { name, value })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Config {
+        toml::from_str(content).unwrap()
+    }
+
+    fn override_from(config: &mut Config, vars: &[(&str, &str)]) -> Result<(), ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        config.override_from(|name| vars.get(name).cloned())
+    }
+
+    /// Returns the field reported invalid by the validation of the config.
+    fn invalid_field(change: impl FnOnce(&mut Config)) -> &'static str {
+        let mut config = Config::default();
+        change(&mut config);
+        match config.validate() {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            result => panic!("unexpected validation result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn example_file_is_valid() {
+        parse(include_str!("../config.example.toml")).validate().unwrap();
+    }
+
+    #[test]
+    fn toml_overrides_defaults() {
+        let config = parse(
+            r#"
+            port = 9000
+            allowed_origins = ["https://example.com"]
+
+            [websocket]
+            max_batch_size = 5
+
+            [websocket.rate_limits.session]
+            Login = { burst = 1, per_second = 0.5 }
+
+            [notifications]
+            mode = "outbox"
+            "#,
+        );
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.allowed_origins, ["https://example.com"]);
+        assert_eq!(config.websocket.max_batch_size, 5);
+        assert_eq!(config.notifications.mode, NotificationMode::Outbox);
+        let session = &config.websocket.rate_limits.session;
+        assert_eq!(session.len(), 1);
+        assert_eq!(session["Login"].burst, 1);
+        assert_eq!(session["Login"].per_second, 0.5);
+
+        // The fields missing from the file keep their defaults.
+        let defaults = Config::default();
+        assert_eq!(config.host, defaults.host);
+        assert_eq!(config.websocket.max_frame_size, defaults.websocket.max_frame_size);
+        assert_eq!(config.websocket.rate_limits.user.len(), 2);
+    }
+
+    #[test]
+    fn toml_rejects_unknown_fields() {
+        assert!(toml::from_str::<Config>("prot = 9000").is_err());
+        assert!(toml::from_str::<Config>("[websocket]\nmax_batch = 5").is_err());
+    }
+
+    #[test]
+    fn missing_file_is_reported() {
+        let path = Path::new("missing-config.toml");
+        match Config::from_file(path) {
+            Err(ConfigError::File(file, _)) => assert_eq!(file, path),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn env_overrides_toml() {
+        let mut config = parse(
+            r#"
+            port = 9000
+            log_level = "debug"
+            "#,
+        );
+        override_from(
+            &mut config,
+            &[
+                ("ACTIX_PORT", "9100"),
+                ("ALLOWED_ORIGINS", "https://a.example, ,https://b.example"),
+                ("LOG_FORMAT", "json"),
+                ("NOTIFICATION_MODE", "outbox"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.port, 9100);
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.notifications.mode, NotificationMode::Outbox);
+        assert_eq!(
+            config.allowed_origins,
+            ["https://a.example", "https://b.example"]
+        );
+    }
+
+    #[test]
+    fn env_rejects_invalid_values() {
+        let mut config = Config::default();
+        match override_from(&mut config, &[("ACTIX_PORT", "eighty")]) {
+            Err(ConfigError::Env { name, value }) => {
+                assert_eq!((name, value.as_str()), ("ACTIX_PORT", "eighty"))
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(override_from(&mut config, &[("LOG_FORMAT", "xml")]).is_err());
+    }
+
+    #[test]
+    fn validation_reports_invalid_fields() {
+        assert_eq!(invalid_field(|config| config.host.clear()), "host");
+        assert_eq!(invalid_field(|config| config.port = 0), "port");
+        assert_eq!(invalid_field(|config| config.workers = 0), "workers");
+        assert_eq!(
+            invalid_field(|config| config.diesel_pool_size = 0),
+            "diesel_pool_size"
+        );
+        assert_eq!(
+            invalid_field(|config| config.sqlx_pool_size = 0),
+            "sqlx_pool_size"
+        );
+        for origin in ["localhost:3000", "https://", "https://example.com/path"] {
+            assert_eq!(
+                invalid_field(|config| config.allowed_origins = vec![origin.to_string()]),
+                "allowed_origins"
+            );
+        }
+        assert_eq!(
+            invalid_field(|config| config.log_level = "verbose".to_string()),
+            "log_level"
+        );
+        assert_eq!(
+            invalid_field(|config| config.websocket.max_frame_size = 0),
+            "websocket.max_frame_size"
+        );
+        assert_eq!(
+            invalid_field(|config| config.websocket.max_payload_size = 1),
+            "websocket.max_payload_size"
+        );
+        assert_eq!(
+            invalid_field(|config| config.websocket.max_batch_size = 0),
+            "websocket.max_batch_size"
+        );
+        assert_eq!(
+            invalid_field(|config| config.notifications.outbox_poll_interval_ms = 0),
+            "notifications.outbox_poll_interval_ms"
+        );
+        assert_eq!(
+            invalid_field(|config| config.notifications.outbox_retention_secs = 0),
+            "notifications.outbox_retention_secs"
+        );
+    }
+
+    #[test]
+    fn validation_reports_invalid_rate_limits() {
+        let limit = RateLimit::new(1, 1.0);
+        assert_eq!(
+            invalid_field(|config| {
+                config
+                    .websocket
+                    .rate_limits
+                    .session
+                    .insert("Unknown".to_string(), limit);
+            }),
+            "websocket.rate_limits.session"
+        );
+        for invalid in [
+            RateLimit::new(0, 1.0),
+            RateLimit::new(1, 0.0),
+            RateLimit::new(1, f64::NAN),
+        ] {
+            assert_eq!(
+                invalid_field(|config| {
+                    config
+                        .websocket
+                        .rate_limits
+                        .user
+                        .insert("Login".to_string(), invalid);
+                }),
+                "websocket.rate_limits.user"
+            );
+        }
+        assert_eq!(
+            invalid_field(|config| config.websocket.rate_limits.max_violations = 0),
+            "websocket.rate_limits.max_violations"
+        );
+    }
+}
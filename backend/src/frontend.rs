@@ -0,0 +1,41 @@
+//! Serving of the frontend built by trunk.
+//!
+//! The backend serves the content of trunk's `dist` directory, so that a
+//! single origin serves both the application and the `/ws` endpoint.
+use std::path::{Path, PathBuf};
+
+use actix_files::{Files, NamedFile};
+use actix_web::dev::{fn_service, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::HttpResponse;
+
+/// Returns the service serving the files of the provided `dist` directory.
+///
+/// The static files are served with the MIME type guessed from their
+/// extension, including `application/wasm` for the WebAssembly binaries.
+/// Requests for paths without an extension that do not match any file are
+/// routes of the single-page application, and are served the `index.html`
+/// file so that the router of the frontend handles them.
+pub fn service(dist: &Path) -> Files {
+    let index: PathBuf = dist.join("index.html");
+    Files::new("/", dist)
+        .index_file("index.html")
+        .default_handler(fn_service(move |req: ServiceRequest| {
+            let index = index.clone();
+            async move {
+                let (req, _) = req.into_parts();
+                let is_route = req.method() == Method::GET
+                    && !req
+                        .path()
+                        .rsplit('/')
+                        .next()
+                        .is_some_and(|segment| segment.contains('.'));
+                let response = if is_route {
+                    NamedFile::open_async(index).await?.into_response(&req)
+                } else {
+                    HttpResponse::NotFound().finish()
+                };
+                Ok(ServiceResponse::new(req, response))
+            }
+        }))
+}
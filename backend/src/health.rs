@@ -0,0 +1,104 @@
+//! Liveness and readiness endpoints.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use actix_web::rt::time::timeout;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool as SQLxPool, Postgres};
+
+use crate::channel_listeners::Listeners;
+use crate::DSDBPool;
+
+/// Maximum time each component has to answer the readiness check.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+struct ComponentStatus {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ComponentStatus {
+    fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl<E: ToString> From<Result<(), E>> for ComponentStatus {
+    fn from(result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => Self {
+                status: "ok",
+                error: None,
+            },
+            Err(err) => Self {
+                status: "unavailable",
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+#[get("/healthz")]
+/// Liveness: the server is up and able to answer requests.
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(Health {
+        status: "ok",
+        components: BTreeMap::new(),
+    })
+}
+
+#[get("/readyz")]
+/// Readiness: the server is able to serve websockets, as both database pools
+/// can hand out connections and the listeners are connected to the channels.
+pub async fn readyz(
+    diesel_pool: web::Data<DSDBPool>,
+    sqlx_pool: web::Data<SQLxPool<Postgres>>,
+    listeners: web::Data<Listeners>,
+) -> impl Responder {
+    let diesel = {
+        let diesel_pool = diesel_pool.clone();
+        web::block(move || diesel_pool.get_timeout(CHECK_TIMEOUT).map(|_| ()))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|result| result.map_err(|err| err.to_string()))
+    };
+
+    let sqlx = timeout(CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(sqlx_pool.get_ref()))
+        .await
+        .map_err(|_| "timed out".to_string())
+        .and_then(|result| result.map(|_| ()).map_err(|err| err.to_string()));
+
+    let listener = if listeners.is_connected() {
+        Ok(())
+    } else {
+        Err("not connected")
+    };
+
+    let components = BTreeMap::from([
+        ("diesel", ComponentStatus::from(diesel)),
+        ("sqlx", ComponentStatus::from(sqlx)),
+        ("listener", ComponentStatus::from(listener)),
+    ]);
+
+    if components.values().all(ComponentStatus::is_ok) {
+        HttpResponse::Ok().json(Health {
+            status: "ok",
+            components,
+        })
+    } else {
+        HttpResponse::ServiceUnavailable().json(Health {
+            status: "unavailable",
+            components,
+        })
+    }
+}
@@ -1,29 +1,51 @@
 use actix_cors::Cors;
-use actix_files::{Files, NamedFile};
 use actix_web::get;
 use actix_web::http::header;
 use actix_web::HttpResponse;
 use actix_web::{middleware::Logger, web, App, Error, HttpRequest, HttpServer};
+use commons::codecs::{Bincode, Cbor, CodecKind, Deflate, Json, MessagePack};
+use commons::messages::{parse_protocol, CloseReason};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager, Pool as DieselPool};
 use sqlx::{postgres::PgPoolOptions, Pool as SQLxPool, Postgres};
+mod config;
 mod models;
 mod schema;
 mod channel_listeners;
+mod frontend;
+mod health;
+mod metrics;
+mod origin;
+mod rate_limits;
+mod sessions;
+mod triggers;
 mod ws;
 
-// #[get("/")]
-// async fn index() -> impl Responder {
-//     NamedFile::open_async("index.html").await.unwrap()
-// }
+/// Query parameters of the websocket upgrade.
+#[derive(serde::Deserialize)]
+struct WebsocketQuery {
+    /// Event after which the client resumes, with the outbox source.
+    last_event_id: Option<i64>,
+}
 
 #[get("/ws")]
 async fn start_websocket(
     req: HttpRequest,
     stream: web::Payload,
     diesel_pool: web::Data<DSDBPool>,
-    sqlx_pool: web::Data<SQLxPool<Postgres>>,
+    listeners: web::Data<channel_listeners::Listeners>,
+    config: web::Data<config::Config>,
+    sessions: web::Data<sessions::Sessions>,
+    user_buckets: web::Data<rate_limits::UserBuckets>,
 ) -> Result<HttpResponse, Error> {
+    if let Err(origin) = origin::check(&req, &config.allowed_origins) {
+        log::warn!("⛔ Rejected websocket upgrade from origin {:?}", origin);
+        metrics::METRICS.rejected_origins.inc();
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let query = web::Query::<WebsocketQuery>::from_query(req.query_string())?;
+
     let diesel_conn = match diesel_pool.get() {
         Ok(diesel_conn) => diesel_conn,
         Err(e) => {
@@ -32,9 +54,133 @@ async fn start_websocket(
         }
     };
 
-    let sqlx_pool = sqlx_pool.get_ref().clone();
+    // The client picks the codec with the subprotocols it offers, or is
+    // spoken JSON when it offers none. Clients speaking another version of
+    // the protocol are closed with a reason once connected, as a rejected
+    // handshake does not tell browsers why. When the client offers other
+    // protocols, one of them is accepted for browsers to complete the
+    // handshake.
+    let offered_protocols: Vec<&str> = req
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| !protocol.is_empty())
+        .collect();
+    let negotiated = offered_protocols
+        .iter()
+        .find_map(|&protocol| Some((protocol, parse_protocol(protocol)?)));
+    let rejected = match (negotiated, offered_protocols.first()) {
+        (None, Some(&offered_protocol)) => {
+            Some(CloseReason::incompatible_protocol(offered_protocol))
+        }
+        _ => None,
+    };
+
+    let connection = ws::Connection {
+        diesel: diesel_conn,
+        listeners: listeners.get_ref().clone(),
+        sessions: sessions.get_ref().clone(),
+        rate_limiter: rate_limits::RateLimiter::new(
+            config.websocket.rate_limits.clone(),
+            user_buckets.get_ref().clone(),
+        ),
+        max_frame_size: config.websocket.max_frame_size,
+        max_payload_size: config.websocket.max_payload_size,
+        batch_window: std::time::Duration::from_millis(config.websocket.batch_window_ms),
+        max_batch_size: config.websocket.max_batch_size,
+        last_event_id: query.last_event_id,
+        rejected,
+        unversioned: offered_protocols.is_empty(),
+    };
+
+    let (accepted_protocol, (codec, compressed)) = match (negotiated, offered_protocols.first()) {
+        (Some((protocol, codec)), _) => (protocol, codec),
+        (None, Some(&offered_protocol)) => (offered_protocol, (CodecKind::Bincode, false)),
+        (None, None) => return ws::start::<Json>(connection, &req, stream, &[]),
+    };
+
+    let start = match (codec, compressed) {
+        (CodecKind::Bincode, false) => ws::start::<Bincode>,
+        (CodecKind::Bincode, true) => ws::start::<Deflate<Bincode>>,
+        (CodecKind::Json, false) => ws::start::<Json>,
+        (CodecKind::Json, true) => ws::start::<Deflate<Json>>,
+        (CodecKind::MessagePack, false) => ws::start::<MessagePack>,
+        (CodecKind::MessagePack, true) => ws::start::<Deflate<MessagePack>>,
+        (CodecKind::Cbor, false) => ws::start::<Cbor>,
+        (CodecKind::Cbor, true) => ws::start::<Deflate<Cbor>>,
+    };
+    start(connection, &req, stream, &[accepted_protocol])
+}
+
+fn notification_source(config: &config::NotificationsConfig) -> channel_listeners::Source {
+    match config.mode {
+        config::NotificationMode::Notify => channel_listeners::Source::Notify,
+        config::NotificationMode::Outbox => channel_listeners::Source::Outbox {
+            poll_interval: std::time::Duration::from_millis(config.outbox_poll_interval_ms),
+        },
+    }
+}
 
-    actix_web_actors::ws::start(ws::WebSocket::new(diesel_conn, sqlx_pool), &req, stream)
+/// Installs the subscriber of the tracing spans and events.
+///
+/// The records of the `log` crate, such as those of actix, are forwarded to
+/// the same subscriber.
+fn init_tracing(config: &config::Config) {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&config.log_level));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match config.log_format {
+        config::LogFormat::Text => subscriber.init(),
+        config::LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+/// Waits for SIGINT or SIGTERM, then closes the websocket sessions and stops the server.
+///
+/// The sessions are closed with a "going away" close frame, hinting the
+/// clients when to reconnect, before the server stops gracefully.
+async fn shutdown_on_signal(
+    server: actix_web::dev::ServerHandle,
+    sessions: sessions::Sessions,
+    retry_after: u64,
+) {
+    let mut terminate =
+        actix_web::rt::signal::unix::signal(actix_web::rt::signal::unix::SignalKind::terminate())
+            .expect("cannot listen to SIGTERM");
+    let interrupt = std::pin::pin!(actix_web::rt::signal::ctrl_c());
+    let terminate = std::pin::pin!(terminate.recv());
+    futures_util::future::select(interrupt, terminate).await;
+    log::info!("🛑 Shutting down the server");
+    let retry_after = Some(std::time::Duration::from_secs(retry_after))
+        .filter(|retry_after| !retry_after.is_zero());
+    sessions.close_all(CloseReason::going_away(retry_after));
+    server.stop(true).await;
+}
+
+/// Writes the migration of the notification triggers, then exits.
+///
+/// The migration is written in the directory named by `MIGRATIONS_DIR`,
+/// `migrations` by default. With `outbox`, the triggers also write the events
+/// read by the outbox source.
+fn generate_triggers(name: Option<&str>, outbox: bool) -> ! {
+    let migrations = std::env::var("MIGRATIONS_DIR").unwrap_or_else(|_| "migrations".to_string());
+    let name = name.unwrap_or(triggers::DEFAULT_MIGRATION_NAME);
+    match triggers::generate(std::path::Path::new(&migrations), name, outbox) {
+        Ok(directory) => {
+            println!("✅ Generated the triggers migration in {}", directory.display());
+            std::process::exit(0);
+        }
+        Err(err) => {
+            eprintln!("🔥 Failed to generate the triggers migration: {}", err);
+            std::process::exit(1);
+        }
+    }
 }
 
 pub(crate) type DSDBPool = DieselPool<ConnectionManager<PgConnection>>;
@@ -42,15 +188,26 @@ pub(crate) type DieselConn = r2d2::PooledConnection<ConnectionManager<PgConnecti
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
     dotenvy::dotenv().ok();
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next().as_deref() == Some("generate-triggers") {
+        let outbox = args.next_if(|arg| arg == "--outbox").is_some();
+        generate_triggers(args.next().as_deref(), outbox);
+    }
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("🔥 Invalid configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+    init_tracing(&config);
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
     // create db connection pool
     let manager = ConnectionManager::<PgConnection>::new(&database_url);
     let diesel_pool: DSDBPool = match r2d2::Pool::builder()
-        // We set the maximum number of connections in the pool to 10
-        .max_size(10)
+        .max_size(config.diesel_pool_size)
         .build(manager)
     {
         Ok(client) => {
@@ -64,7 +221,7 @@ async fn main() -> std::io::Result<()> {
     };
 
     let sqlx_pool: SQLxPool<Postgres> = match PgPoolOptions::new()
-        .max_connections(10)
+        .max_connections(config.sqlx_pool_size)
         .connect(&database_url)
         .await
     {
@@ -78,15 +235,59 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    log::info!("starting HTTP server at http://localhost:8080");
+    if config.notifications.mode == config::NotificationMode::Outbox {
+        match channel_listeners::check_outbox(&sqlx_pool).await {
+            Ok(true) => {}
+            Ok(false) => {
+                log::error!(
+                    "🔥 The outbox mode needs the triggers of `generate-triggers --outbox`"
+                );
+                std::process::exit(1);
+            }
+            Err(err) => {
+                log::error!("🔥 Failed to check the outbox: {}", err);
+                std::process::exit(1);
+            }
+        }
+        actix_web::rt::spawn(channel_listeners::prune_events(
+            sqlx_pool.clone(),
+            std::time::Duration::from_secs(config.notifications.outbox_retention_secs),
+        ));
+    }
 
-    HttpServer::new(move || {
-        // Since in the development we are currently using Trunk, we need to
-        // support CROSS ORIGIN RESOURCE SHARING (CORS) for the frontend
-        // to be able to connect to the backend.
+    let listeners = match channel_listeners::Listeners::new(
+        sqlx_pool.clone(),
+        notification_source(&config.notifications),
+    )
+    .await
+    {
+        Ok(listeners) => listeners,
+        Err(err) => {
+            log::error!("🔥 Failed to start listening to the channels: {}", err);
+            std::process::exit(1);
+        }
+    };
+    actix_web::rt::spawn(listeners.clone().run());
+
+    log::info!(
+        "starting HTTP server at http://{}:{}",
+        config.host,
+        config.port
+    );
 
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
+    let bind_address = (config.host.clone(), config.port);
+    let workers = config.workers;
+    let shutdown_retry_after = config.websocket.shutdown_retry_after;
+    let config = web::Data::new(config);
+    let sessions = sessions::Sessions::default();
+    let shutdown_sessions = sessions.clone();
+    let user_buckets = rate_limits::UserBuckets::default();
+
+    let server = HttpServer::new(move || {
+        let cors = config
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
             .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
             .allowed_headers(vec![
                 header::CONTENT_TYPE,
@@ -102,15 +303,32 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(diesel_pool.clone()))
             // pass in the SQLx database pool to all routes
             .app_data(web::Data::new(sqlx_pool.clone()))
-            // .service(index)
+            // pass in the listeners of the channels, shared by the websocket sessions
+            .app_data(web::Data::new(listeners.clone()))
+            .app_data(config.clone())
+            // pass in the registry of the websocket sessions, closed on shutdown
+            .app_data(web::Data::new(sessions.clone()))
+            // pass in the rate limits of the users, shared by their websocket sessions
+            .app_data(web::Data::new(user_buckets.clone()))
             .service(start_websocket)
-            // .service(Files::new("/static", "./static"))
+            .service(health::healthz)
+            .service(health::readyz)
+            .service(metrics::metrics)
+            // The frontend is registered last, as it matches all the other paths
+            .service(frontend::service(&config.frontend_dist))
             .wrap(Logger::default())
     })
-    .workers(2)
-    .bind(("localhost", 8080))?
-    .run()
-    .await?;
+    .workers(workers)
+    // The signals are handled by `shutdown_on_signal`, which closes the websockets first
+    .disable_signals()
+    .bind(bind_address)?
+    .run();
+
+    actix_web::rt::spawn(shutdown_on_signal(
+        server.handle(),
+        shutdown_sessions,
+        shutdown_retry_after,
+    ));
 
-    Ok(())
+    server.await
 }
@@ -0,0 +1,176 @@
+//! Prometheus metrics of the websocket sessions and of the notifications.
+use std::sync::LazyLock;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use prometheus::{
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::{Pool as SQLxPool, Postgres};
+
+use crate::DSDBPool;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Number of open websocket actors.
+    pub websockets: IntGauge,
+    /// Messages received from the frontends, per variant.
+    pub frontend_messages: IntCounterVec,
+    /// Messages sent to the frontends, per variant.
+    pub backend_messages: IntCounterVec,
+    /// Notifications received from Postgres, per channel name.
+    pub notifications: IntCounterVec,
+    /// Frames received from the frontends that could not be decoded.
+    pub decode_errors: IntCounter,
+    /// Websocket upgrades rejected because of their origin.
+    pub rejected_origins: IntCounter,
+    /// Messages received from the frontends refused by the rate limits, per variant.
+    pub rate_limited: IntCounterVec,
+    /// Number of notification listeners running for the websocket actors.
+    pub pg_handlers: IntGauge,
+    /// Connections of the database pools, per pool and state.
+    pool_connections: IntGaugeVec,
+    /// Maximum number of connections of the database pools, per pool.
+    pool_max_connections: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let websockets = IntGauge::new("websockets_open", "Number of open websockets").unwrap();
+        let frontend_messages = IntCounterVec::new(
+            Opts::new(
+                "websocket_frontend_messages_total",
+                "Messages received from the frontends",
+            ),
+            &["variant"],
+        )
+        .unwrap();
+        let backend_messages = IntCounterVec::new(
+            Opts::new(
+                "websocket_backend_messages_total",
+                "Messages sent to the frontends",
+            ),
+            &["variant"],
+        )
+        .unwrap();
+        let notifications = IntCounterVec::new(
+            Opts::new(
+                "pg_notifications_total",
+                "Notifications received from Postgres",
+            ),
+            &["channel"],
+        )
+        .unwrap();
+        let decode_errors = IntCounter::new(
+            "websocket_decode_errors_total",
+            "Frames received from the frontends that could not be decoded",
+        )
+        .unwrap();
+        let rejected_origins = IntCounter::new(
+            "websocket_rejected_origins_total",
+            "Websocket upgrades rejected because of their origin",
+        )
+        .unwrap();
+        let rate_limited = IntCounterVec::new(
+            Opts::new(
+                "websocket_rate_limited_total",
+                "Messages received from the frontends refused by the rate limits",
+            ),
+            &["variant"],
+        )
+        .unwrap();
+        let pg_handlers = IntGauge::new(
+            "pg_handlers_active",
+            "Notification listeners running for the websockets",
+        )
+        .unwrap();
+        let pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Connections of the database pools"),
+            &["pool", "state"],
+        )
+        .unwrap();
+        let pool_max_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_max_connections",
+                "Maximum number of connections of the database pools",
+            ),
+            &["pool"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(websockets.clone())).unwrap();
+        registry.register(Box::new(frontend_messages.clone())).unwrap();
+        registry.register(Box::new(backend_messages.clone())).unwrap();
+        registry.register(Box::new(notifications.clone())).unwrap();
+        registry.register(Box::new(decode_errors.clone())).unwrap();
+        registry.register(Box::new(rejected_origins.clone())).unwrap();
+        registry.register(Box::new(rate_limited.clone())).unwrap();
+        registry.register(Box::new(pg_handlers.clone())).unwrap();
+        registry.register(Box::new(pool_connections.clone())).unwrap();
+        registry.register(Box::new(pool_max_connections.clone())).unwrap();
+
+        Self {
+            registry,
+            websockets,
+            frontend_messages,
+            backend_messages,
+            notifications,
+            decode_errors,
+            rejected_origins,
+            rate_limited,
+            pg_handlers,
+            pool_connections,
+            pool_max_connections,
+        }
+    }
+
+    fn set_pool(&self, pool: &str, idle: usize, in_use: usize, max: usize) {
+        self.pool_connections
+            .with_label_values(&[pool, "idle"])
+            .set(idle as i64);
+        self.pool_connections
+            .with_label_values(&[pool, "in_use"])
+            .set(in_use as i64);
+        self.pool_max_connections
+            .with_label_values(&[pool])
+            .set(max as i64);
+    }
+}
+
+#[get("/metrics")]
+/// Exposes the metrics in the Prometheus text format.
+pub async fn metrics(
+    diesel_pool: web::Data<DSDBPool>,
+    sqlx_pool: web::Data<SQLxPool<Postgres>>,
+) -> impl Responder {
+    // The utilization of the pools is sampled upon scraping.
+    let diesel_state = diesel_pool.state();
+    METRICS.set_pool(
+        "diesel",
+        diesel_state.idle_connections as usize,
+        (diesel_state.connections - diesel_state.idle_connections) as usize,
+        diesel_pool.max_size() as usize,
+    );
+    let sqlx_size = sqlx_pool.size() as usize;
+    let sqlx_idle = sqlx_pool.num_idle();
+    METRICS.set_pool(
+        "sqlx",
+        sqlx_idle,
+        sqlx_size.saturating_sub(sqlx_idle),
+        sqlx_pool.options().get_max_connections() as usize,
+    );
+
+    let mut buffer = Vec::new();
+    match TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(buffer),
+        Err(err) => {
+            log::error!("🔥 Error encoding the metrics: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
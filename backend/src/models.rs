@@ -23,13 +23,15 @@ impl From<commons::comments::Comment> for Comment {
 }
 
 impl Comment {
-    pub fn delete(&self, conn: &mut PgConnection) -> QueryResult<usize> {
+    /// Deletes the comment if it belongs to the provided user, returning the
+    /// number of deleted rows.
+    pub fn delete(&self, owner_id: i32, conn: &mut PgConnection) -> QueryResult<usize> {
         use crate::schema::comments::dsl::*;
-        diesel::delete(comments.filter(id.eq(self.id))).execute(conn)
+        diesel::delete(comments.filter(id.eq(self.id).and(user_id.eq(owner_id)))).execute(conn)
     }
 }
 
-#[derive(Queryable, Debug, Clone)]
+#[derive(Queryable, QueryableByName, Debug, Clone)]
 #[diesel(table_name = users)]
 pub struct User {
     pub id: i32,
@@ -78,6 +80,59 @@ impl Into<commons::comments::Comment> for Comment {
     }
 }
 
+#[derive(Queryable, Debug, Clone)]
+#[diesel(table_name = sessions)]
+pub struct Session {
+    pub token: uuid::Uuid,
+    pub user_id: i32,
+}
+
+impl Session {
+    /// Returns the session with the provided token, alongside its user.
+    ///
+    /// Tokens that are not valid UUIDs cannot match any session.
+    pub fn find(token: &str, conn: &mut PgConnection) -> QueryResult<Option<(Session, User)>> {
+        let Ok(session_token) = uuid::Uuid::parse_str(token) else {
+            return Ok(None);
+        };
+        sessions::table
+            .inner_join(users::table)
+            .filter(sessions::token.eq(session_token))
+            .first::<(Session, User)>(conn)
+            .optional()
+    }
+
+    pub fn delete(&self, conn: &mut PgConnection) -> QueryResult<usize> {
+        diesel::delete(sessions::table.filter(sessions::token.eq(self.token))).execute(conn)
+    }
+
+    pub fn into_commons(self, user: User) -> commons::users::Session {
+        commons::users::Session {
+            user: user.into(),
+            token: self.token.to_string(),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sessions)]
+pub struct NewSession {
+    pub user_id: i32,
+}
+
+impl NewSession {
+    pub fn new(user_id: i32) -> Self {
+        Self { user_id }
+    }
+
+    pub fn insert(&self, conn: &mut PgConnection) -> QueryResult<Session> {
+        use crate::schema::sessions::dsl::*;
+        diesel::insert_into(sessions)
+            .values(self)
+            .get_result::<Session>(conn)
+    }
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = users)]
 pub struct NewUser {
@@ -98,11 +153,21 @@ impl NewUser {
             .get_result::<User>(conn)
     }
 
+    /// Inserts the user, or returns the existing user with the same username.
+    ///
+    /// Usernames are compared case-insensitively, as enforced by the unique
+    /// index on `LOWER(username)`, and the upsert is atomic so concurrent
+    /// logins cannot create duplicated users.
     pub fn insert_or_get(&self, conn: &mut PgConnection) -> QueryResult<User> {
-        use crate::schema::users::dsl::*;
-        match users.filter(username.eq(&self.username)).first::<User>(conn) {
-            Ok(user) => Ok(user),
-            Err(_) => self.insert(conn),
-        }
+        // Diesel's `on_conflict` only supports plain columns as conflict
+        // targets, so we need to write the query for the expression index.
+        // The no-op update makes the existing row be returned on conflicts.
+        diesel::sql_query(
+            "INSERT INTO users (username) VALUES ($1) \
+             ON CONFLICT ((LOWER(username))) DO UPDATE SET username = users.username \
+             RETURNING id, username",
+        )
+        .bind::<diesel::sql_types::Varchar, _>(&self.username)
+        .get_result::<User>(conn)
     }
 }
@@ -0,0 +1,42 @@
+//! Checks of the origin of the websocket upgrades.
+//!
+//! Browsers do not apply CORS to websockets, so any page could otherwise open
+//! a websocket to the backend with the cookies of its visitors.
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+/// Checks that the request comes from an allowed origin, returning the
+/// rejected origin otherwise.
+///
+/// The allowed origins are those allowed by CORS and the origin of the backend
+/// itself, which serves the frontend. Requests without an `Origin` header do
+/// not come from browsers, and are allowed.
+pub fn check(req: &HttpRequest, allowed_origins: &[String]) -> Result<(), String> {
+    let Some(origin) = req.headers().get(header::ORIGIN) else {
+        return Ok(());
+    };
+    let Ok(origin) = origin.to_str() else {
+        return Err(String::from_utf8_lossy(origin.as_bytes()).into_owned());
+    };
+
+    if allowed_origins
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+        || is_same_origin(req, origin)
+    {
+        Ok(())
+    } else {
+        Err(origin.to_string())
+    }
+}
+
+fn is_same_origin(req: &HttpRequest, origin: &str) -> bool {
+    let connection = req.connection_info();
+    let Some(host) = origin
+        .strip_prefix(connection.scheme())
+        .and_then(|origin| origin.strip_prefix("://"))
+    else {
+        return false;
+    };
+    host.eq_ignore_ascii_case(connection.host())
+}
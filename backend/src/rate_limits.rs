@@ -0,0 +1,250 @@
+//! Token bucket rate limiting of the messages received from the frontends.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{RateLimit, RateLimitsConfig};
+
+/// Number of user buckets above which the full ones are dropped.
+const USER_BUCKETS_PRUNING_THRESHOLD: usize = 1024;
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.per_second)
+            .min(self.limit.burst as f64);
+        self.updated_at = now;
+    }
+
+    /// Takes a token, or returns the delay until one is available.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.limit.per_second,
+            ))
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.limit.burst as f64
+    }
+}
+
+/// Buckets of the users, shared by all the websocket sessions.
+#[derive(Clone, Default)]
+pub struct UserBuckets {
+    buckets: Arc<Mutex<HashMap<(i32, String), TokenBucket>>>,
+}
+
+impl UserBuckets {
+    fn take(
+        &self,
+        user_id: i32,
+        kind: &str,
+        limit: RateLimit,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= USER_BUCKETS_PRUNING_THRESHOLD {
+            // A full bucket is equivalent to a missing one.
+            buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+        buckets
+            .entry((user_id, kind.to_string()))
+            .or_insert_with(|| TokenBucket::full(limit, now))
+            .take(now)
+    }
+}
+
+/// Rate limiter of a websocket session.
+pub struct RateLimiter {
+    limits: RateLimitsConfig,
+    session: HashMap<String, TokenBucket>,
+    users: UserBuckets,
+    /// Consecutive messages that exceeded the limits.
+    violations: u32,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimitsConfig, users: UserBuckets) -> Self {
+        Self {
+            limits,
+            session: HashMap::new(),
+            users,
+            violations: 0,
+        }
+    }
+
+    /// Checks whether a message of the provided kind may be handled, or
+    /// returns the delay until it may.
+    ///
+    /// The user limits only apply once the session is logged in.
+    pub fn check(&mut self, kind: &str, user_id: Option<i32>) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut result = match self.limits.session.get(kind) {
+            Some(&limit) => self
+                .session
+                .entry(kind.to_string())
+                .or_insert_with(|| TokenBucket::full(limit, now))
+                .take(now),
+            None => Ok(()),
+        };
+        if let (Ok(()), Some(user_id), Some(&limit)) =
+            (&result, user_id, self.limits.user.get(kind))
+        {
+            result = self.users.take(user_id, kind, limit, now);
+        }
+
+        match result {
+            Ok(()) => self.violations = 0,
+            Err(_) => self.violations += 1,
+        }
+        result
+    }
+
+    /// Returns whether the session exceeded the limits too many times in a row.
+    pub fn is_abusive(&self) -> bool {
+        self.violations >= self.limits.max_violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        burst: 2,
+        per_second: 0.5,
+    };
+
+    #[test]
+    fn bucket_refills_up_to_its_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, start);
+        assert_eq!(bucket.take(start), Ok(()));
+        assert_eq!(bucket.take(start), Ok(()));
+        assert!(bucket.take(start).is_err());
+
+        let later = start + Duration::from_secs(2);
+        assert_eq!(bucket.take(later), Ok(()));
+        assert!(bucket.take(later).is_err());
+
+        assert!(bucket.is_full(later + Duration::from_secs(60)));
+        assert_eq!(bucket.tokens, LIMIT.burst as f64);
+    }
+
+    #[test]
+    fn bucket_returns_delay_until_next_token() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, start);
+        bucket.take(start).unwrap();
+        bucket.take(start).unwrap();
+        assert_eq!(bucket.take(start), Err(Duration::from_secs(2)));
+        assert_eq!(
+            bucket.take(start + Duration::from_millis(500)),
+            Err(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn user_buckets_prune_full_buckets() {
+        let start = Instant::now();
+        let buckets = UserBuckets::default();
+        for user_id in 0..USER_BUCKETS_PRUNING_THRESHOLD as i32 {
+            buckets.take(user_id, "Login", LIMIT, start).unwrap();
+        }
+        assert_eq!(
+            buckets.buckets.lock().unwrap().len(),
+            USER_BUCKETS_PRUNING_THRESHOLD
+        );
+
+        // Only the bucket of the key taken after the refill is left.
+        let refilled = start + Duration::from_secs(60);
+        buckets.take(0, "Login", LIMIT, refilled).unwrap();
+        assert_eq!(buckets.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn user_buckets_do_not_prune_partial_buckets() {
+        let start = Instant::now();
+        let buckets = UserBuckets::default();
+        for user_id in 0..USER_BUCKETS_PRUNING_THRESHOLD as i32 {
+            buckets.take(user_id, "Login", LIMIT, start).unwrap();
+        }
+        buckets.take(0, "Login", LIMIT, start).unwrap();
+        assert_eq!(
+            buckets.buckets.lock().unwrap().len(),
+            USER_BUCKETS_PRUNING_THRESHOLD
+        );
+    }
+
+    fn limiter(users: &UserBuckets) -> RateLimiter {
+        let limits = RateLimitsConfig {
+            session: HashMap::from([("InsertComment".to_string(), LIMIT)]),
+            user: HashMap::from([("DeleteComment".to_string(), LIMIT)]),
+            max_violations: 2,
+        };
+        RateLimiter::new(limits, users.clone())
+    }
+
+    #[test]
+    fn session_limits_are_per_connection() {
+        let users = UserBuckets::default();
+        let mut rate_limiter = limiter(&users);
+        assert_eq!(rate_limiter.check("InsertComment", None), Ok(()));
+        assert_eq!(rate_limiter.check("InsertComment", None), Ok(()));
+
+        assert!(rate_limiter.check("InsertComment", None).is_err());
+
+        let mut other = limiter(&users);
+        assert_eq!(other.check("InsertComment", None), Ok(()));
+    }
+
+    #[test]
+    fn user_limits_apply_once_logged_in() {
+        let users = UserBuckets::default();
+        let mut rate_limiter = limiter(&users);
+        for _ in 0..3 {
+            assert_eq!(rate_limiter.check("DeleteComment", None), Ok(()));
+        }
+        assert_eq!(rate_limiter.check("DeleteComment", Some(1)), Ok(()));
+        assert_eq!(rate_limiter.check("DeleteComment", Some(1)), Ok(()));
+        assert!(rate_limiter.check("DeleteComment", Some(1)).is_err());
+        assert_eq!(rate_limiter.check("DeleteComment", Some(2)), Ok(()));
+    }
+
+    #[test]
+    fn consecutive_violations_are_abusive() {
+        let users = UserBuckets::default();
+        let mut rate_limiter = limiter(&users);
+        rate_limiter.check("InsertComment", None).unwrap();
+        rate_limiter.check("InsertComment", None).unwrap();
+
+        assert!(rate_limiter.check("InsertComment", None).is_err());
+        assert!(!rate_limiter.is_abusive());
+        rate_limiter.check("Login", None).unwrap();
+        assert!(rate_limiter.check("InsertComment", None).is_err());
+        assert!(!rate_limiter.is_abusive());
+        assert!(rate_limiter.check("InsertComment", None).is_err());
+        assert!(rate_limiter.is_abusive());
+    }
+}
@@ -8,6 +8,13 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    sessions (token) {
+        token -> Uuid,
+        user_id -> Int4,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -17,8 +24,10 @@ diesel::table! {
 }
 
 diesel::joinable!(comments -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     comments,
+    sessions,
     users,
 );
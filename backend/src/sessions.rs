@@ -0,0 +1,36 @@
+//! Registry of the open websocket sessions, used to close them all on shutdown.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use actix::Recipient;
+use commons::messages::CloseReason;
+use uuid::Uuid;
+
+use crate::ws::Close;
+
+#[derive(Clone, Default)]
+pub struct Sessions {
+    addresses: Arc<Mutex<HashMap<Uuid, Recipient<Close>>>>,
+}
+
+impl Sessions {
+    pub fn register(&self, connection_id: Uuid, address: Recipient<Close>) {
+        self.addresses
+            .lock()
+            .unwrap()
+            .insert(connection_id, address);
+    }
+
+    pub fn unregister(&self, connection_id: &Uuid) {
+        self.addresses.lock().unwrap().remove(connection_id);
+    }
+
+    /// Closes all the open sessions with the provided reason.
+    pub fn close_all(&self, reason: CloseReason) {
+        let addresses = std::mem::take(&mut *self.addresses.lock().unwrap());
+        tracing::info!("Closing {} websocket sessions", addresses.len());
+        for address in addresses.into_values() {
+            address.do_send(Close(reason.clone()));
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Generator of the migrations creating the triggers that notify the channels.
+//!
+//! The triggers are built from the [`Channel`] definitions: the table they
+//! are attached to, the columns the channel names are built from and the
+//! fields of the payload, so that the SQL cannot drift from the Rust types.
+//! The migrations are generated with `backend generate-triggers [--outbox] [name]`.
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+use crate::channel_listeners::{Channel, CommentsChannel, CommentsUserChannel, Route};
+
+/// Size in bytes from which `pg_notify` rejects the payloads.
+pub const MAX_PAYLOAD_SIZE: usize = 8000;
+
+/// Name of the migration when none is provided.
+pub const DEFAULT_MIGRATION_NAME: &str = "notify_triggers";
+
+/// Notification trigger of a channel.
+#[derive(Debug, Clone)]
+pub struct ChannelTrigger {
+    table: &'static str,
+    key: &'static str,
+    route: &'static [Route],
+    fields: &'static [&'static str],
+}
+
+impl ChannelTrigger {
+    pub fn of<Ch: Channel>() -> Self {
+        Self {
+            table: Ch::TABLE,
+            key: Ch::KEY,
+            route: Ch::ROUTE,
+            fields: payload_fields::<Ch::Payload>(),
+        }
+    }
+
+    /// Expression of the name of the channel, built from the `OLD` or `NEW`
+    /// row.
+    fn channel_expression(&self, row: &str) -> String {
+        if let [Route::Literal(literal)] = self.route {
+            return quote_literal(literal);
+        }
+        let parts: Vec<String> = self
+            .route
+            .iter()
+            .map(|part| match part {
+                Route::Literal(literal) => quote_literal(literal),
+                Route::Column(column) => format!("{}.{}::text", row, column),
+            })
+            .collect();
+        format!("CONCAT({})", parts.join(", "))
+    }
+
+    /// Expression of the fields of the `OLD` or `NEW` row.
+    fn row_expression(&self, row: &str) -> String {
+        let arguments: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| format!("{}, {}.{}", quote_literal(field), row, field))
+            .collect();
+        format!("json_build_object({})", arguments.join(", "))
+    }
+
+    /// Expression of the payload, with the action type and the rows before
+    /// and after the change, `NULL` on INSERT and DELETE respectively.
+    fn payload_expression(&self) -> String {
+        format!(
+            "json_build_object(\n      'action_type', TG_OP,\n      \
+             'old', CASE WHEN TG_OP = 'INSERT' THEN NULL ELSE {} END,\n      \
+             'new', CASE WHEN TG_OP = 'DELETE' THEN NULL ELSE {} END\n    )::text",
+            self.row_expression("OLD"),
+            self.row_expression("NEW"),
+        )
+    }
+
+    /// Expression of the payload sent instead of a too large one, with only
+    /// the keys of the rows, marked as truncated.
+    fn truncated_payload_expression(&self) -> String {
+        let key = |row: &str| {
+            format!(
+                "json_build_object({}, {}.{})",
+                quote_literal(self.key),
+                row,
+                self.key
+            )
+        };
+        format!(
+            "json_build_object(\n        'action_type', TG_OP,\n        \
+             'truncated', true,\n        \
+             'old', CASE WHEN TG_OP = 'INSERT' THEN NULL ELSE {} END,\n        \
+             'new', CASE WHEN TG_OP = 'DELETE' THEN NULL ELSE {} END\n      )::text",
+            key("OLD"),
+            key("NEW"),
+        )
+    }
+
+    /// Query returning the payload of the row whose key is the one of the
+    /// JSON object bound to `$1`, as sent in the truncated payloads.
+    pub fn fetch_query(&self) -> String {
+        // The key is read from the object as a row of the table, so that it
+        // has the type of the column.
+        format!(
+            "SELECT {}::text FROM {table} WHERE {key} = \
+             (json_populate_record(NULL::{table}, $1::json)).{key}",
+            self.row_expression(self.table),
+            table = self.table,
+            key = self.key,
+        )
+    }
+
+    /// Statements notifying the channel of the change.
+    ///
+    /// A routed channel is notified with the new row, and with the old one on
+    /// DELETE or when the UPDATE moves the row to another channel, so that
+    /// both channels learn about it.
+    fn notify_statements(&self, outbox: bool) -> String {
+        if !self
+            .route
+            .iter()
+            .any(|part| matches!(part, Route::Column(_)))
+        {
+            return notify(&self.channel_expression("NEW"), outbox, "  ");
+        }
+        let (old, new) = (
+            self.channel_expression("OLD"),
+            self.channel_expression("NEW"),
+        );
+        format!(
+            "  IF TG_OP <> 'DELETE' THEN\n{}  END IF;\n\
+             \x20 IF TG_OP = 'DELETE'\n    \
+             OR (TG_OP = 'UPDATE' AND {old} IS DISTINCT FROM {new}) THEN\n{}  END IF;\n",
+            notify(&new, outbox, "    "),
+            notify(&old, outbox, "    "),
+        )
+    }
+}
+
+/// Statements notifying a channel of the `payload`, and in the outbox mode
+/// writing the complete `event` to the `events` table beforehand.
+fn notify(channel: &str, outbox: bool, indent: &str) -> String {
+    let mut sql = String::new();
+    if outbox {
+        let _ = writeln!(
+            sql,
+            "{indent}INSERT INTO events (channel, payload) VALUES ({channel}, event);"
+        );
+    }
+    let _ = writeln!(sql, "{indent}PERFORM pg_notify({channel}, payload);");
+    sql
+}
+
+/// Triggers of all the channels listened to by the backend.
+pub fn channel_triggers() -> Vec<ChannelTrigger> {
+    vec![
+        ChannelTrigger::of::<CommentsChannel>(),
+        ChannelTrigger::of::<CommentsUserChannel>(),
+    ]
+}
+
+/// SQL creating the notification function and trigger of each table.
+///
+/// In the outbox mode, the triggers also write the events to the `events`
+/// table, created if needed, which the backend tails instead of listening to
+/// the notifications, only used to wake it up.
+pub fn up_sql(triggers: &[ChannelTrigger], outbox: bool) -> String {
+    let mut sql = String::from(HEADER);
+    if outbox {
+        sql.push_str(EVENTS_TABLE);
+    }
+    for (table, triggers) in by_table(triggers) {
+        let _ = write!(
+            sql,
+            "\nCREATE OR REPLACE FUNCTION notify_{table}() RETURNS TRIGGER AS $$\n\
+             DECLARE\n  payload TEXT;\n{}BEGIN\n",
+            if outbox { "  event TEXT;\n" } else { "" },
+        );
+        // The payload is only built again for the channels whose payloads
+        // differ from the previous one.
+        let mut payload = None;
+        for trigger in triggers {
+            let expressions = (
+                trigger.payload_expression(),
+                trigger.truncated_payload_expression(),
+            );
+            if payload.as_ref() != Some(&expressions) {
+                // The events are written complete, as only the notifications
+                // are limited in size.
+                if outbox {
+                    let _ = write!(sql, "  event := {};\n  payload := event;\n", expressions.0);
+                } else {
+                    let _ = writeln!(sql, "  payload := {};", expressions.0);
+                }
+                let _ = write!(
+                    sql,
+                    "  IF octet_length(payload) >= {} THEN\n    payload := {};\n  END IF;\n",
+                    MAX_PAYLOAD_SIZE, expressions.1,
+                );
+                payload = Some(expressions);
+            }
+            sql.push_str(&trigger.notify_statements(outbox));
+        }
+        let _ = write!(
+            sql,
+            "  RETURN NULL;\nEND;\n$$ LANGUAGE plpgsql;\n\n\
+             DROP TRIGGER IF EXISTS {table}_notify ON {table};\n\
+             CREATE TRIGGER {table}_notify AFTER INSERT OR UPDATE OR DELETE ON {table}\n  \
+             FOR EACH ROW EXECUTE PROCEDURE notify_{table}();\n",
+        );
+    }
+    sql
+}
+
+/// SQL dropping what [`up_sql`] creates.
+pub fn down_sql(triggers: &[ChannelTrigger]) -> String {
+    let mut sql = String::from(HEADER);
+    for table in by_table(triggers).keys() {
+        let _ = write!(
+            sql,
+            "\nDROP TRIGGER IF EXISTS {table}_notify ON {table};\n\
+             DROP FUNCTION IF EXISTS notify_{table}();\n",
+        );
+    }
+    sql
+}
+
+/// Writes a new migration with the triggers of all the channels in the
+/// migrations directory, returning the directory of the migration.
+///
+/// The migration is reverted by restoring the triggers of the previous
+/// generated migration, if any, and by dropping them otherwise.
+pub fn generate(migrations: &Path, name: &str, outbox: bool) -> std::io::Result<PathBuf> {
+    let triggers = channel_triggers();
+    let down = match previous_up_sql(migrations)? {
+        Some(previous) => previous,
+        None => down_sql(&triggers),
+    };
+    let directory = migrations.join(format!(
+        "{}_{}",
+        chrono::Local::now().format("%Y-%m-%d-%H%M%S"),
+        name
+    ));
+    std::fs::create_dir(&directory)?;
+    std::fs::write(directory.join("up.sql"), up_sql(&triggers, outbox))?;
+    std::fs::write(directory.join("down.sql"), down)?;
+    Ok(directory)
+}
+
+/// Returns the `up.sql` of the latest generated migration.
+fn previous_up_sql(migrations: &Path) -> std::io::Result<Option<String>> {
+    let mut directories = std::fs::read_dir(migrations)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    directories.sort();
+    for directory in directories.iter().rev() {
+        match std::fs::read_to_string(directory.join("up.sql")) {
+            Ok(sql) if sql.starts_with(HEADER) => return Ok(Some(sql)),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+const HEADER: &str = "-- Generated by `backend generate-triggers`, do not edit.\n";
+
+/// Table of the events written by the triggers in the outbox mode.
+///
+/// The events are tailed in the order of the transactions that wrote them,
+/// as the ids are assigned before the transactions commit, in any order.
+const EVENTS_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS events (
+  id BIGSERIAL PRIMARY KEY,
+  txid BIGINT NOT NULL DEFAULT pg_current_xact_id()::text::bigint,
+  channel TEXT NOT NULL,
+  payload TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS events_position ON events (channel, txid, id);
+CREATE INDEX IF NOT EXISTS events_created_at ON events (created_at);
+";
+
+fn by_table(triggers: &[ChannelTrigger]) -> BTreeMap<&'static str, Vec<&ChannelTrigger>> {
+    let mut tables: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for trigger in triggers {
+        tables.entry(trigger.table).or_default().push(trigger);
+    }
+    tables
+}
+
+fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
+
+/// Names of the fields of a payload struct, as expected by its `Deserialize`.
+fn payload_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    match T::deserialize(FieldsDeserializer) {
+        Err(FieldsError::Fields(fields)) => fields,
+        _ => panic!("the payload {} is not a struct", std::any::type_name::<T>()),
+    }
+}
+
+/// Deserializer failing with the fields of the struct it is asked for.
+struct FieldsDeserializer;
+
+#[derive(Debug)]
+enum FieldsError {
+    Fields(&'static [&'static str]),
+    Other,
+}
+
+impl fmt::Display for FieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not a struct")
+    }
+}
+
+impl std::error::Error for FieldsError {}
+
+impl de::Error for FieldsError {
+    fn custom<T: fmt::Display>(_: T) -> Self {
+        FieldsError::Other
+    }
+}
+
+impl<'de> Deserializer<'de> for FieldsDeserializer {
+    type Error = FieldsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(FieldsError::Other)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(FieldsError::Fields(fields))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that the latest generated migration matches the channels, in
+    /// either notification mode.
+    #[test]
+    fn latest_migration_is_up_to_date() {
+        let migrations = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let latest = previous_up_sql(&migrations)
+            .unwrap()
+            .expect("no generated migration");
+        let triggers = channel_triggers();
+        assert!(
+            [false, true]
+                .map(|outbox| up_sql(&triggers, outbox))
+                .contains(&latest),
+            "the latest generated migration is out of date, generate a new one with \
+             `cargo run -p backend -- generate-triggers`"
+        );
+    }
+}
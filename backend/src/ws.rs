@@ -1,177 +1,628 @@
 //! Websocket backend
 use std::collections::HashMap;
-use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::time::Duration;
 
 use actix::ActorContext;
 use actix::AsyncContext;
 use actix::SpawnHandle;
 use actix::WrapFuture;
 use actix::{Actor, StreamHandler};
+use actix_http::ws::Item;
+use actix_web::web::{self, Bytes, BytesMut};
+use actix_web::{Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use commons::messages::{BackendMessage, FrontendMessage};
-use sqlx::{Pool as SQLxPool, Postgres};
+use commons::codecs::Codec;
+use commons::comments::Comment;
+use commons::messages::{BackendMessage, CloseReason, DecodeError, FrontendMessage, RateLimited};
+use tracing::field::Empty;
+use tracing::Instrument;
 
 use crate::channel_listeners::*;
+use crate::metrics::METRICS;
+use crate::rate_limits::RateLimiter;
+use crate::sessions::Sessions;
 use crate::DieselConn;
 
-pub struct WebSocket {
+/// State of a new websocket, independent of the codec it speaks.
+pub struct Connection {
+    pub diesel: DieselConn,
+    pub listeners: Listeners,
+    pub sessions: Sessions,
+    pub rate_limiter: RateLimiter,
+    /// Maximum size in bytes of the frames.
+    pub max_frame_size: usize,
+    /// Maximum size in bytes of the messages, including those split in continuation frames.
+    pub max_payload_size: usize,
+    /// Duration during which the events are coalesced into a batch, or zero
+    /// to send them one by one.
+    pub batch_window: Duration,
+    /// Maximum number of events in a batch.
+    pub max_batch_size: usize,
+    /// Event after which the client resumes, as provided with the
+    /// `last_event_id` query parameter.
+    pub last_event_id: Option<i64>,
+    /// Reason to close the websocket with as soon as it starts, such as when
+    /// the client speaks another version of the protocol.
+    pub rejected: Option<CloseReason>,
+    /// Whether the client offered no subprotocol, in which case it is spoken
+    /// JSON and rejected when sending binary frames, as the unversioned
+    /// clients preceding the subprotocol do.
+    pub unversioned: bool,
+}
+
+/// Starts the websocket actor speaking the codec `C`.
+pub fn start<C: Codec>(
+    connection: Connection,
+    req: &HttpRequest,
+    stream: web::Payload,
+    protocols: &[&str],
+) -> Result<HttpResponse, Error> {
+    let codec = actix_http::ws::Codec::new().max_size(connection.max_frame_size);
+    ws::WsResponseBuilder::new(WebSocket::<C>::new(connection), req, stream)
+        .protocols(protocols)
+        .codec(codec)
+        .start()
+}
+
+pub struct WebSocket<C> {
+    connection_id: uuid::Uuid,
     pg_handlers: HashMap<String, SpawnHandle>,
+    session: Option<crate::models::Session>,
+    user: Option<commons::users::User>,
     diesel: DieselConn,
-    sqlx: SQLxPool<Postgres>,
+    listeners: Listeners,
+    sessions: Sessions,
+    rate_limiter: RateLimiter,
+    /// Maximum size in bytes of the messages, including those split in continuation frames.
+    max_payload_size: usize,
+    /// Message being assembled from continuation frames.
+    continuation: Option<BytesMut>,
+    batch_window: Duration,
+    max_batch_size: usize,
+    /// Events waiting for the end of the batch window to be sent.
+    batch: Vec<BackendMessage>,
+    /// Timer sending the batch at the end of its window.
+    batch_timer: Option<SpawnHandle>,
+    /// Position of the client in the events of the outbox.
+    cursor: Cursor,
+    /// Reason to close the websocket with as soon as it starts.
+    rejected: Option<CloseReason>,
+    unversioned: bool,
+    /// Span of the session, identified by a connection id and, once logged in, the user id.
+    span: tracing::Span,
+    // The codec is only used through its associated functions.
+    codec: PhantomData<fn() -> C>,
 }
 
-impl WebSocket {
-    pub fn new(diesel: DieselConn, sqlx: SQLxPool<Postgres>) -> Self {
+impl<C: Codec> WebSocket<C> {
+    pub fn new(connection: Connection) -> Self {
+        let Connection {
+            diesel,
+            listeners,
+            sessions,
+            rate_limiter,
+            max_frame_size: _,
+            max_payload_size,
+            batch_window,
+            max_batch_size,
+            last_event_id,
+            rejected,
+            unversioned,
+        } = connection;
+        let connection_id = uuid::Uuid::new_v4();
         Self {
+            connection_id,
             pg_handlers: HashMap::new(),
+            session: None,
+            user: None,
             diesel,
-            sqlx,
+            listeners,
+            sessions,
+            rate_limiter,
+            max_payload_size,
+            continuation: None,
+            batch_window,
+            max_batch_size,
+            batch: Vec::new(),
+            batch_timer: None,
+            cursor: Cursor::new(last_event_id),
+            rejected,
+            unversioned,
+            span: tracing::info_span!(
+                "websocket",
+                connection_id = %connection_id,
+                codec = C::NAME,
+                compressed = C::COMPRESSED,
+                user_id = Empty
+            ),
+            codec: PhantomData,
         }
     }
+
+    /// Notifies the frontend of the session and starts listening to the user channel.
+    fn logged_in(
+        &mut self,
+        session: crate::models::Session,
+        user: crate::models::User,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        self.send(
+            ctx,
+            BackendMessage::LoggedIn(session.clone().into_commons(user.clone())),
+        );
+
+        // A different user may have been logged in on this socket.
+        if self
+            .user
+            .as_ref()
+            .is_some_and(|previous| previous.id != user.id)
+        {
+            self.stop_listening_to_user(ctx);
+        }
+        self.span.record("user_id", user.id);
+        self.session = Some(session);
+        self.user = Some(user.clone().into());
+
+        let recipient = ctx.address();
+        let user_id = user.id;
+        self.listen(
+            ctx,
+            CommentsUserChannel::new(user.into()),
+            move |notification: Notification<Comment>| {
+                // An UPDATE may give the comment to another user, which is
+                // then a deletion for one of them and an insertion for the
+                // other.
+                // The owner of a truncated row is unknown, which is taken as
+                // owned since the channel was notified.
+                let owned = |row: &Row<Comment>| match row {
+                    Row::Full(comment) => comment.user_id == user_id,
+                    Row::Truncated(_) => true,
+                };
+                let message = match (
+                    notification.old.filter(owned),
+                    notification.new.filter(owned),
+                ) {
+                    (None, Some(Row::Full(new))) => BackendMessage::InsertedComment(new),
+                    (Some(_), Some(Row::Full(new))) => BackendMessage::UpdatedOwnComment(new),
+                    // A truncated new row is gone from the table.
+                    (Some(Row::Full(old)), _) => BackendMessage::DeletedOwnComment(old),
+                    (Some(Row::Truncated(key)), _) => match serde_json::from_value(key) {
+                        Ok(id) => BackendMessage::DeletedOwnCommentId(id),
+                        Err(_) => return,
+                    },
+                    (None, _) => return,
+                };
+                recipient.do_send(message);
+            },
+        );
+    }
+
+    /// Sends the provided message to the frontend, after the queued events
+    /// so that it does not overtake them.
+    fn send(&mut self, ctx: &mut <Self as Actor>::Context, msg: BackendMessage) {
+        self.flush(ctx);
+        self.write(ctx, msg);
+    }
+
+    /// Writes the provided message to the websocket.
+    fn write(&self, ctx: &mut <Self as Actor>::Context, msg: BackendMessage) {
+        METRICS
+            .backend_messages
+            .with_label_values(&[msg.kind()])
+            .inc();
+        match C::encode(&msg) {
+            Ok(bytes) if C::TEXT => match String::from_utf8(bytes) {
+                Ok(text) => ctx.text(text),
+                Err(err) => tracing::error!("Error encoding {} message: {}", msg.kind(), err),
+            },
+            Ok(bytes) => ctx.binary(bytes),
+            Err(err) => tracing::error!("Error encoding {} message: {}", msg.kind(), err),
+        }
+    }
+
+    /// Queues an event for the frontend, to be sent along with the events
+    /// arriving within the batch window.
+    fn queue(&mut self, ctx: &mut <Self as Actor>::Context, msg: BackendMessage) {
+        if self.batch_window.is_zero() {
+            return self.write(ctx, msg);
+        }
+        self.batch.push(msg);
+        if self.batch.len() >= self.max_batch_size {
+            self.flush(ctx);
+        } else if self.batch_timer.is_none() {
+            self.batch_timer = Some(ctx.run_later(self.batch_window, |act, ctx| {
+                act.batch_timer = None;
+                act.flush(ctx);
+            }));
+        }
+    }
+
+    /// Sends the queued events, in a batch unless there is a single one.
+    fn flush(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(handle) = self.batch_timer.take() {
+            ctx.cancel_future(handle);
+        }
+        let mut batch = std::mem::take(&mut self.batch);
+        match batch.len() {
+            0 => {}
+            1 => self.write(ctx, batch.pop().unwrap()),
+            _ => {
+                for msg in &batch {
+                    METRICS
+                        .backend_messages
+                        .with_label_values(&[msg.kind()])
+                        .inc();
+                }
+                self.write(ctx, BackendMessage::Batch(batch));
+            }
+        }
+    }
+
+    /// Starts listening to the provided channel, unless already listening to it.
+    fn listen<Ch>(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        channel: Ch,
+        call_back: impl FnMut(Notification<Ch::Payload>) + 'static,
+    ) where
+        Ch: Channel + 'static,
+    {
+        let name = channel.to_string();
+        if self.pg_handlers.contains_key(&name) {
+            return;
+        }
+        let (listeners, cursor) = (self.listeners.clone(), self.cursor.clone());
+        let recipient = ctx.address();
+        let give_back = move |id| recipient.do_send(BackendMessage::LastEventId(id));
+        let handle = ctx.spawn(
+            async move {
+                start_listening(&listeners, &cursor, channel, call_back, give_back).await;
+            }
+            .instrument(self.span.clone())
+            .into_actor(self),
+        );
+        self.pg_handlers.insert(name, handle);
+        METRICS.pg_handlers.inc();
+    }
+
+    /// Invalidates the current session and stops listening to the user channel.
+    fn logged_out(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(session) = self.session.take() {
+            if let Err(err) = session.delete(&mut self.diesel) {
+                tracing::error!("Error deleting session: {:?}", err);
+            }
+        }
+        self.stop_listening_to_user(ctx);
+        self.user = None;
+        self.span.record("user_id", Empty);
+    }
+
+    fn stop_listening_to_user(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(user) = self.user.clone() {
+            if let Some(handle) = self
+                .pg_handlers
+                .remove(&CommentsUserChannel::new(user).to_string())
+            {
+                ctx.cancel_future(handle);
+                METRICS.pg_handlers.dec();
+            }
+        }
+    }
+
+    /// Refuses a message exceeding the rate limits, closing the websocket
+    /// when the limits keep being exceeded.
+    fn rate_limited(
+        &mut self,
+        ctx: &mut <Self as Actor>::Context,
+        kind: &'static str,
+        retry_after: std::time::Duration,
+    ) {
+        METRICS.rate_limited.with_label_values(&[kind]).inc();
+        if self.rate_limiter.is_abusive() {
+            tracing::warn!("Closing websocket exceeding the rate limits");
+            // A new session starts with full buckets, so the client is asked to
+            // wait for the limits before reconnecting.
+            return self.close(ctx, CloseReason::policy_violation(retry_after));
+        }
+        tracing::debug!(
+            "Message exceeding the rate limits, retry after {:?}",
+            retry_after
+        );
+        self.send(
+            ctx,
+            BackendMessage::RateLimited(RateLimited {
+                kind: kind.to_string(),
+                retry_after,
+            }),
+        );
+    }
+
+    /// Closes the websocket of a client that cannot be spoken to.
+    fn reject(&mut self, ctx: &mut <Self as Actor>::Context, reason: CloseReason) {
+        self.span
+            .in_scope(|| tracing::warn!("Rejecting websocket: {:?}", reason));
+        self.close(ctx, reason);
+    }
+
+    /// Closes the websocket after receiving a message larger than allowed.
+    fn too_large(&mut self, ctx: &mut <Self as Actor>::Context) {
+        tracing::warn!("Closing websocket receiving a frame or message too large");
+        METRICS.decode_errors.inc();
+        self.close(
+            ctx,
+            CloseReason::new(
+                CloseReason::MESSAGE_TOO_BIG,
+                Some("message too big".to_string()),
+            ),
+        );
+    }
+
+    /// Closes the websocket with the provided reason, after sending the
+    /// queued events.
+    fn close(&mut self, ctx: &mut <Self as Actor>::Context, reason: CloseReason) {
+        self.flush(ctx);
+        self.stop_listening(ctx);
+        ctx.close(Some(reason.into()));
+        ctx.stop();
+    }
+
+    /// Assembles the message split in continuation frames, returning it once complete.
+    fn continue_message(&mut self, item: Item) -> Result<Option<Bytes>, DecodeError> {
+        let (first, bytes, last) = match item {
+            Item::FirstText(bytes) if C::TEXT => (true, bytes, false),
+            Item::FirstText(_) => return Err(DecodeError::UnexpectedFrame("text")),
+            Item::FirstBinary(bytes) => (true, bytes, false),
+            Item::Continue(bytes) => (false, bytes, false),
+            Item::Last(bytes) => (false, bytes, true),
+        };
+        if first {
+            self.continuation = Some(BytesMut::new());
+        }
+        let buffer = self
+            .continuation
+            .as_mut()
+            .ok_or(DecodeError::UnexpectedFrame("continuation"))?;
+        if buffer.len() + bytes.len() > self.max_payload_size {
+            self.continuation = None;
+            return Err(DecodeError::TooLarge(self.max_payload_size as u64));
+        }
+        buffer.extend_from_slice(&bytes);
+        Ok(if last {
+            self.continuation.take().map(BytesMut::freeze)
+        } else {
+            None
+        })
+    }
+
+    /// Stops listening to all the channels.
+    fn stop_listening(&mut self, ctx: &mut <Self as Actor>::Context) {
+        for (_, handle) in self.pg_handlers.drain() {
+            ctx.cancel_future(handle);
+            METRICS.pg_handlers.dec();
+        }
+    }
+}
+
+/// Closes the session with the provided reason, as when the server shuts down.
+pub struct Close(pub CloseReason);
+
+impl actix::Message for Close {
+    type Result = ();
 }
 
-impl Actor for WebSocket {
+impl<C: Codec> Actor for WebSocket<C> {
     type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.sessions
+            .register(self.connection_id, ctx.address().recipient());
+        METRICS.websockets.inc();
+        self.span.in_scope(|| tracing::info!("Websocket opened"));
+        if let Some(reason) = self.rejected.take() {
+            return self.reject(ctx, reason);
+        }
+
+        let recipient = ctx.address();
+        self.listen(
+            ctx,
+            CommentsChannel,
+            move |notification: Notification<Comment>| {
+                let message = match notification.action_type {
+                    ActionType::INSERT => BackendMessage::NewComment,
+                    ActionType::UPDATE => BackendMessage::UpdatedComment,
+                    ActionType::DELETE => BackendMessage::DeletedComment,
+                };
+                let deleted = matches!(notification.action_type, ActionType::DELETE);
+                match notification.into_record() {
+                    Some(Row::Full(comment)) => recipient.do_send(message(comment)),
+                    // Only the key of a truncated deleted row is left, while
+                    // the other truncated rows are gone from the table.
+                    Some(Row::Truncated(key)) if deleted => {
+                        if let Ok(id) = serde_json::from_value(key) {
+                            recipient.do_send(BackendMessage::DeletedCommentId(id));
+                        }
+                    }
+                    _ => {}
+                }
+            },
+        );
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.span.in_scope(|| tracing::info!("Websocket closed"));
+        self.sessions.unregister(&self.connection_id);
+        METRICS.websockets.dec();
+        // The listeners are stopped alongside the actor.
+        METRICS.pg_handlers.sub(self.pg_handlers.len() as i64);
+    }
 }
 
-impl actix::Handler<BackendMessage> for WebSocket {
+impl<C: Codec> actix::Handler<BackendMessage> for WebSocket<C> {
     type Result = ();
 
     fn handle(&mut self, msg: BackendMessage, ctx: &mut Self::Context) {
-        ctx.binary(msg);
+        let _span = tracing::debug_span!(parent: &self.span, "backend_message", kind = msg.kind())
+            .entered();
+        self.queue(ctx, msg);
     }
 }
 
-impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocket {
+impl<C: Codec> actix::Handler<Close> for WebSocket<C> {
+    type Result = ();
+
+    fn handle(&mut self, Close(reason): Close, ctx: &mut Self::Context) {
+        self.span
+            .in_scope(|| tracing::info!("Closing websocket: {:?}", reason));
+        self.close(ctx, reason);
+    }
+}
+
+impl<C: Codec> StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocket<C> {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        {
-            let recipient = ctx.address();
-            let sqlx = self.sqlx.clone();
-
-            if !self.pg_handlers.contains_key(&CommentsChannel.to_string()) {
-                self.pg_handlers.insert(
-                    CommentsChannel.to_string(),
-                    ctx.spawn(
-                        async move {
-                            let _ = start_listening(
-                                &sqlx,
-                                CommentsChannel,
-                                |payload: CommentsPayload| {
-                                    match payload.action_type {
-                                        ActionType::INSERT => {
-                                            recipient.do_send(BackendMessage::NewComment(
-                                                payload.into(),
-                                            ));
-                                        }
-                                        ActionType::UPDATE => {
-                                            recipient.do_send(BackendMessage::UpdatedComment(
-                                                payload.into(),
-                                            ));
-                                        }
-                                        ActionType::DELETE => {
-                                            recipient.do_send(BackendMessage::DeletedComment(
-                                                payload.into(),
-                                            ));
-                                        }
-                                    };
-                                },
-                            )
-                            .await;
-                        }
-                        .into_actor(self),
-                    ),
-                );
-            }
-        }
+        let span = tracing::info_span!(parent: &self.span, "frame", kind = Empty);
+        let _span = span.enter();
 
         match msg {
+            Ok(ws::Message::Ping(bytes)) => {
+                ctx.pong(&bytes);
+            }
+            Ok(ws::Message::Pong(_)) => {}
+            Ok(ws::Message::Binary(_) | ws::Message::Continuation(Item::FirstBinary(_)))
+                if self.unversioned =>
+            {
+                self.reject(ctx, CloseReason::incompatible_protocol("none"))
+            }
             Ok(msg) => {
-                log::info!("Got message from WebSocket: {:?}", msg);
-                let frontend_message: FrontendMessage = msg.into();
+                let msg = match msg {
+                    ws::Message::Continuation(item) => match self.continue_message(item) {
+                        Ok(Some(bytes)) => ws::Message::Binary(bytes),
+                        Ok(None) => return,
+                        Err(DecodeError::TooLarge(_)) => return self.too_large(ctx),
+                        Err(err) => {
+                            METRICS.decode_errors.inc();
+                            tracing::error!("Error decoding message from WebSocket: {}", err);
+                            return;
+                        }
+                    },
+                    msg => msg,
+                };
+                let frontend_message =
+                    match FrontendMessage::decode::<C>(msg, self.max_payload_size as u64) {
+                        Ok(frontend_message) => frontend_message,
+                        Err(DecodeError::TooLarge(_)) => return self.too_large(ctx),
+                        Err(err) => {
+                            METRICS.decode_errors.inc();
+                            tracing::error!("Error decoding message from WebSocket: {}", err);
+                            return;
+                        }
+                    };
+                span.record("kind", frontend_message.kind());
+                tracing::debug!("Got message from WebSocket: {:?}", frontend_message);
+                METRICS
+                    .frontend_messages
+                    .with_label_values(&[frontend_message.kind()])
+                    .inc();
+                let user_id = self.user.as_ref().map(|user| user.id);
+                if let Err(retry_after) = self.rate_limiter.check(frontend_message.kind(), user_id)
+                {
+                    self.rate_limited(ctx, frontend_message.kind(), retry_after);
+                    return;
+                }
                 match frontend_message {
                     FrontendMessage::Login(username) => {
+                        if let Err(err) = commons::users::validate_username(&username) {
+                            self.send(ctx, BackendMessage::LoginRejected(err));
+                            return;
+                        }
+
                         // We insert the user into the database
                         let new_user = crate::models::NewUser {
                             username: username.clone(),
                         };
 
-                        match new_user.insert_or_get(&mut self.diesel) {
-                            Ok(user) => {
-                                ctx.binary(BackendMessage::LoggedIn(user.clone().into()));
-
-                                let recipient = ctx.address();
-                                let sqlx = self.sqlx.clone();
-                                if !self.pg_handlers.contains_key(
-                                    &CommentsUserChannel::new(user.clone().into()).to_string(),
-                                ) {
-                                    self.pg_handlers.insert(
-                                        CommentsUserChannel::new(user.clone().into()).to_string(),
-                                        ctx.spawn(
-                                            async move {
-                                                let _ = start_listening(
-                                                    &sqlx,
-                                                    CommentsUserChannel::new(user.into()),
-                                                    |payload: CommentsPayload| {
-                                                        match payload.action_type {
-                                                            ActionType::INSERT => {
-                                                                recipient.do_send(
-                                                                    BackendMessage::InsertedComment(
-                                                                        payload.into(),
-                                                                    ),
-                                                                );
-                                                            }
-                                                            _ => {}
-                                                        };
-                                                    },
-                                                )
-                                                .await;
-                                            }
-                                            .into_actor(self),
-                                        ),
-                                    );
-                                }
+                        match new_user.insert_or_get(&mut self.diesel).and_then(|user| {
+                            crate::models::NewSession::new(user.id)
+                                .insert(&mut self.diesel)
+                                .map(|session| (session, user))
+                        }) {
+                            Ok((session, user)) => {
+                                self.logged_in(session, user, ctx);
                             }
                             Err(err) => {
-                                log::error!("Error inserting user: {:?}", err);
+                                tracing::error!("Error inserting user: {:?}", err);
                             }
                         };
                     }
-                    FrontendMessage::InsertComment((user, comment_text)) => {
-                        let new_comment = crate::models::NewComment {
-                            user_id: user.id,
-                            body: comment_text,
+                    FrontendMessage::Resume(token) => {
+                        match crate::models::Session::find(&token, &mut self.diesel) {
+                            Ok(Some((session, user))) => {
+                                self.logged_in(session, user, ctx);
+                            }
+                            Ok(None) => {
+                                self.send(ctx, BackendMessage::SessionExpired);
+                            }
+                            Err(err) => {
+                                tracing::error!("Error retrieving session: {:?}", err);
+                            }
+                        }
+                    }
+                    FrontendMessage::Logout => {
+                        self.logged_out(ctx);
+                    }
+                    FrontendMessage::InsertComment(comment_text) => {
+                        let Some(user_id) = user_id else {
+                            self.send(ctx, BackendMessage::LoginRequired);
+                            return;
+                        };
+                        let body = match commons::comments::validate_body(&comment_text) {
+                            Ok(body) => body,
+                            Err(err) => {
+                                self.send(ctx, BackendMessage::CommentRejected(err));
+                                return;
+                            }
                         };
 
+                        let new_comment = crate::models::NewComment { user_id, body };
+
                         match new_comment.insert(&mut self.diesel) {
                             Ok(_) => {}
                             Err(err) => {
-                                log::error!("Error inserting comment: {:?}", err);
+                                tracing::error!("Error inserting comment: {:?}", err);
                             }
                         }
                     }
                     FrontendMessage::DeleteComment(comment) => {
+                        let Some(user_id) = user_id else {
+                            self.send(ctx, BackendMessage::LoginRequired);
+                            return;
+                        };
                         let comment: crate::models::Comment = comment.into();
-                        match comment.delete(&mut self.diesel) {
+                        match comment.delete(user_id, &mut self.diesel) {
+                            Ok(0) => {
+                                tracing::warn!(
+                                    "Comment {} not deleted, gone or of another user",
+                                    comment.id
+                                );
+                            }
                             Ok(_) => {
                                 // We could trigger the event here,
                                 // but we want to handle it separately in the
                                 // pg_notify handler
                             }
                             Err(err) => {
-                                log::error!("Error deleting comment: {:?}", err);
+                                tracing::error!("Error deleting comment: {:?}", err);
                             }
                         }
                     }
-                    FrontendMessage::Close(code) => {
+                    FrontendMessage::Close(_reason) => {
                         ctx.stop();
                     }
-                    _ => {
-                        log::error!("Unhandled message: {:?}", frontend_message);
-                    }
                 }
             }
+            Err(ws::ProtocolError::Overflow) => self.too_large(ctx),
             Err(err) => {
-                log::error!("Error reading from WebSocket: {:?}", err);
+                tracing::error!("Error reading from WebSocket: {:?}", err);
                 ctx.stop();
             }
         }
@@ -0,0 +1,299 @@
+//! Derive macro implementing the `Channel` trait of the backend.
+//!
+//! ```ignore
+//! #[derive(Channel)]
+//! #[channel(
+//!     name = "comments_{user.id}",
+//!     payload = Comment,
+//!     table = "comments",
+//!     columns = ["user_id"]
+//! )]
+//! pub struct CommentsUserChannel {
+//!     pub user: User,
+//! }
+//! ```
+//!
+//! The name of the channel is a template, where `{field}` or `{field.path}`
+//! placeholders are replaced by the fields of the struct, and `{{` and `}}`
+//! stand for literal braces. The placeholders must refer to existing fields.
+//!
+//! The channel is notified of the changes of the rows of `table`, whose
+//! `columns` replace the placeholders, in order, when the triggers build the
+//! name of the channel from a row. The rows are identified by their `key`
+//! column, `id` unless set with `key = "..."`.
+//!
+//! The name of the kind of channel, used to label the metrics, defaults to the
+//! template with each placeholder replaced by the name of its field, such as
+//! `comments_user`, and can be set with `label = "..."`.
+//!
+//! The generated code implements `crate::channel_listeners::Channel`, so the
+//! macro is meant to be used in the backend. Another path to the module of the
+//! `Channel` trait and `Route` enum can be set with `module = "..."`.
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Expr, ExprArray, ExprLit, Fields, Ident, Lit, LitStr,
+    Path, Type,
+};
+
+#[proc_macro_derive(Channel, attributes(channel))]
+pub fn derive_channel(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Arguments of the `#[channel(...)]` attribute.
+struct ChannelAttribute {
+    name: LitStr,
+    payload: Type,
+    table: LitStr,
+    columns: Vec<LitStr>,
+    key: Option<LitStr>,
+    label: Option<LitStr>,
+    module: Option<Path>,
+}
+
+impl ChannelAttribute {
+    fn from_input(input: &DeriveInput) -> syn::Result<Self> {
+        let attribute = input
+            .attrs
+            .iter()
+            .find(|attribute| attribute.path().is_ident("channel"))
+            .ok_or_else(|| {
+                syn::Error::new(
+                    Span::call_site(),
+                    "missing #[channel(name = \"...\", payload = Type, table = \"...\")] attribute",
+                )
+            })?;
+
+        let mut name = None;
+        let mut payload = None;
+        let mut table = None;
+        let mut columns = Vec::new();
+        let mut key = None;
+        let mut label = None;
+        let mut module = None;
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("payload") {
+                payload = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("table") {
+                table = Some(sql_identifier(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("columns") {
+                let array: ExprArray = meta.value()?.parse()?;
+                for column in array.elems {
+                    match column {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(column),
+                            ..
+                        }) => columns.push(sql_identifier(column)?),
+                        column => {
+                            return Err(syn::Error::new_spanned(column, "expected a column name"))
+                        }
+                    }
+                }
+            } else if meta.path.is_ident("key") {
+                key = Some(sql_identifier(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("label") {
+                label = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("module") {
+                module = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else {
+                return Err(meta.error(
+                    "expected `name`, `payload`, `table`, `columns`, `key`, `label` or `module`",
+                ));
+            }
+            Ok(())
+        })?;
+
+        Ok(Self {
+            name: name.ok_or_else(|| syn::Error::new_spanned(attribute, "missing `name`"))?,
+            payload: payload
+                .ok_or_else(|| syn::Error::new_spanned(attribute, "missing `payload`"))?,
+            table: table.ok_or_else(|| syn::Error::new_spanned(attribute, "missing `table`"))?,
+            columns,
+            key,
+            label,
+            module,
+        })
+    }
+}
+
+/// Checks that a table or column name is a plain SQL identifier, as they are
+/// written as is in the triggers.
+fn sql_identifier(name: LitStr) -> syn::Result<LitStr> {
+    let value = name.value();
+    let valid = value
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_lowercase() || first == '_')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(name)
+    } else {
+        Err(syn::Error::new(
+            name.span(),
+            format!("`{}` is not a lowercase SQL identifier", value),
+        ))
+    }
+}
+
+/// Part of a channel name template.
+enum Segment {
+    Literal(String),
+    /// Path of fields, starting with a field of the struct.
+    Placeholder(Vec<Ident>),
+}
+
+/// Parses a channel name template into its literal parts and placeholders.
+fn parse_template(template: &LitStr) -> syn::Result<Vec<Segment>> {
+    let value = template.value();
+    let error = |message: String| syn::Error::new(template.span(), message);
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(character) => placeholder.push(character),
+                        None => return Err(error("unclosed `{` in the channel name".to_string())),
+                    }
+                }
+                let path = placeholder
+                    .split('.')
+                    .map(|segment| {
+                        syn::parse_str::<Ident>(segment.trim()).map_err(|_| {
+                            error(format!("invalid placeholder `{{{}}}`", placeholder))
+                        })
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(path));
+            }
+            '}' => return Err(error("unmatched `}` in the channel name".to_string())),
+            character => literal.push(character),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let attribute = ChannelAttribute::from_input(&input)?;
+    let fields: Vec<&Ident> = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().flat_map(|f| &f.ident).collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "channels must be structs with named fields or unit structs",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "channels must be structs",
+            ))
+        }
+    };
+
+    let module = attribute
+        .module
+        .clone()
+        .unwrap_or_else(|| syn::parse_quote!(crate::channel_listeners));
+    let template = &attribute.name;
+    let mut format = String::new();
+    let mut arguments = Vec::new();
+    let mut label = String::new();
+    let mut route = Vec::new();
+    let mut columns = attribute.columns.iter();
+    for segment in parse_template(template)? {
+        match segment {
+            Segment::Literal(literal) => {
+                format.push_str(&literal.replace('{', "{{").replace('}', "}}"));
+                label.push_str(&literal);
+                route.push(quote!(#module::Route::Literal(#literal)));
+            }
+            Segment::Placeholder(path) => {
+                let field = &path[0];
+                if !fields.contains(&field) {
+                    return Err(syn::Error::new(
+                        template.span(),
+                        format!("no field `{}` in `{}`", field, input.ident),
+                    ));
+                }
+                // The nested fields are checked by the compiler, reporting
+                // their errors on the template.
+                let path = path
+                    .iter()
+                    .map(|ident| format_ident!("{}", ident, span = template.span()));
+                arguments.push(quote_spanned!(template.span()=> self.#(#path).*));
+                format.push_str("{}");
+                label.push_str(&field.to_string());
+                let column = columns.next().ok_or_else(|| {
+                    syn::Error::new(
+                        template.span(),
+                        format!("no column in `columns` for the placeholder `{}`", field),
+                    )
+                })?;
+                route.push(quote!(#module::Route::Column(#column)));
+            }
+        }
+    }
+    if let Some(column) = columns.next() {
+        return Err(syn::Error::new(
+            column.span(),
+            "more columns than placeholders in the channel name",
+        ));
+    }
+    let label = attribute
+        .label
+        .unwrap_or_else(|| LitStr::new(&label, template.span()));
+
+    let ident = &input.ident;
+    let payload = &attribute.payload;
+    let table = &attribute.table;
+    let key = attribute
+        .key
+        .unwrap_or_else(|| LitStr::new("id", Span::call_site()));
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::std::fmt::Display for #ident #type_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                ::std::write!(f, #format #(, #arguments)*)
+            }
+        }
+
+        impl #impl_generics #module::Channel for #ident #type_generics #where_clause {
+            const NAME: &'static str = #label;
+            const TABLE: &'static str = #table;
+            const KEY: &'static str = #key;
+            const ROUTE: &'static [#module::Route] = &[#(#route),*];
+            type Payload = #payload;
+        }
+    })
+}
@@ -0,0 +1,9 @@
+//! Checks the channels accepted by the derive macro, and the errors reported
+//! for the others.
+
+#[test]
+fn ui() {
+    let tests = trybuild::TestCases::new();
+    tests.pass("tests/ui/pass/*.rs");
+    tests.compile_fail("tests/ui/fail/*.rs");
+}
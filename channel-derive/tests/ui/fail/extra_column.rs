@@ -0,0 +1,7 @@
+use channel_derive::Channel;
+
+#[derive(Channel)]
+#[channel(name = "comments", payload = (), table = "comments", columns = ["user_id"])]
+pub struct CommentsChannel;
+
+fn main() {}
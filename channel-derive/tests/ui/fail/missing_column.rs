@@ -0,0 +1,9 @@
+use channel_derive::Channel;
+
+#[derive(Channel)]
+#[channel(name = "comments_{user}", payload = (), table = "comments")]
+pub struct CommentsUserChannel {
+    pub user: i32,
+}
+
+fn main() {}
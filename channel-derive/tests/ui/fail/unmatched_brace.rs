@@ -0,0 +1,7 @@
+use channel_derive::Channel;
+
+#[derive(Channel)]
+#[channel(name = "comments_user}", payload = (), table = "comments")]
+pub struct CommentsUserChannel;
+
+fn main() {}
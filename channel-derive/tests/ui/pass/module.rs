@@ -0,0 +1,52 @@
+use channel_derive::Channel;
+
+mod listeners {
+    pub trait Channel: std::fmt::Display {
+        const NAME: &'static str;
+        const TABLE: &'static str;
+        const KEY: &'static str;
+        const ROUTE: &'static [Route];
+        type Payload;
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum Route {
+        Literal(&'static str),
+        Column(&'static str),
+    }
+}
+
+pub struct User {
+    pub id: i32,
+}
+
+#[derive(Channel)]
+#[channel(
+    name = "comments_{user.id}_{{all}}",
+    payload = (),
+    table = "comments",
+    columns = ["user_id"],
+    module = "listeners"
+)]
+pub struct CommentsUserChannel {
+    pub user: User,
+}
+
+fn main() {
+    use listeners::{Channel, Route};
+
+    let channel = CommentsUserChannel {
+        user: User { id: 3 },
+    };
+    assert_eq!(channel.to_string(), "comments_3_{all}");
+    assert_eq!(CommentsUserChannel::NAME, "comments_user_{all}");
+    assert_eq!(CommentsUserChannel::KEY, "id");
+    assert_eq!(
+        CommentsUserChannel::ROUTE,
+        [
+            Route::Literal("comments_"),
+            Route::Column("user_id"),
+            Route::Literal("_{all}"),
+        ]
+    );
+}
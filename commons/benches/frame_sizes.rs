@@ -0,0 +1,68 @@
+//! Compares the size of the frames of a comment history snapshot with the
+//! different codecs, with and without compression.
+//!
+//! Run with `cargo bench -p commons --bench frame_sizes`.
+use commons::codecs::{Bincode, Cbor, Codec, Deflate, Json, MessagePack};
+use commons::comments::Comment;
+use commons::messages::BackendMessage;
+
+/// Words the bodies of the comments are made of, to compress as text would.
+const WORDS: &str = "the websocket comment frontend backend message is a of and \
+                     snapshot history user sent to with frame size large small";
+
+/// Returns a snapshot of the provided number of comments of varying length.
+fn snapshot(comments: usize) -> BackendMessage {
+    let words: Vec<&str> = WORDS.split_whitespace().collect();
+    // A linear congruential generator keeps the snapshots reproducible.
+    let mut state: u32 = 42;
+    let mut next = move |bound: usize| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) as usize % bound
+    };
+    BackendMessage::Comments(
+        (0..comments)
+            .map(|id| Comment {
+                id: id as i32,
+                user_id: next(50) as i32,
+                body: (0..5 + next(60))
+                    .map(|_| words[next(words.len())])
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect(),
+    )
+}
+
+/// Returns the size in bytes of the message encoded by a codec.
+type Size = fn(&BackendMessage) -> usize;
+
+fn size<C: Codec>(message: &BackendMessage) -> usize {
+    C::encode(message).expect("encodable message").len()
+}
+
+fn main() {
+    let codecs: [(&str, Size); 8] = [
+        ("bincode", size::<Bincode>),
+        ("bincode+deflate", size::<Deflate<Bincode>>),
+        ("json", size::<Json>),
+        ("json+deflate", size::<Deflate<Json>>),
+        ("msgpack", size::<MessagePack>),
+        ("msgpack+deflate", size::<Deflate<MessagePack>>),
+        ("cbor", size::<Cbor>),
+        ("cbor+deflate", size::<Deflate<Cbor>>),
+    ];
+    let counts = [1, 10, 100, 1000];
+
+    print!("{:<16}", "comments");
+    for count in counts {
+        print!("{:>12}", count);
+    }
+    println!();
+    for (name, size) in codecs {
+        print!("{:<16}", name);
+        for count in counts {
+            print!("{:>12}", size(&snapshot(count)));
+        }
+        println!();
+    }
+}
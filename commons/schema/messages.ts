@@ -0,0 +1,39 @@
+// Messages of the ayw.v7 protocol, generated by `protocol-schema`.
+
+export type User = { id: number, username: string, };
+
+/**
+ * A logged-in user, alongside the token identifying the session.
+ */
+export type Session = { user: User, token: string, };
+
+/**
+ * Reasons why a username is not acceptable.
+ */
+export type UsernameError = "TooShort" | "TooLong" | { "InvalidCharacter": string } | "Reserved";
+
+export type Comment = { id: number, user_id: number, body: string, };
+
+/**
+ * Reasons why a comment body is not acceptable.
+ */
+export type CommentBodyError = "Empty" | "TooLong" | "ControlCharacter";
+
+export type CloseReason = { code: number, reason: string | null, };
+
+/**
+ * A message refused because too many messages of its kind were sent.
+ */
+export type RateLimited = { 
+/**
+ * Kind of the refused message, as returned by [`FrontendMessage::kind`].
+ */
+kind: string, 
+/**
+ * Delay after which a message of the same kind is accepted again.
+ */
+retry_after: { secs: number, nanos: number }, };
+
+export type FrontendMessage = { "type": "Close", "data": CloseReason | null } | { "type": "Login", "data": string } | { "type": "Resume", "data": string } | { "type": "Logout" } | { "type": "InsertComment", "data": string } | { "type": "DeleteComment", "data": Comment };
+
+export type BackendMessage = { "type": "LoggedIn", "data": Session } | { "type": "LoginRejected", "data": UsernameError } | { "type": "SessionExpired" } | { "type": "NewComment", "data": Comment } | { "type": "UpdatedComment", "data": Comment } | { "type": "InsertedComment", "data": Comment } | { "type": "Comments", "data": Array<Comment> } | { "type": "DeletedComment", "data": Comment } | { "type": "UpdatedOwnComment", "data": Comment } | { "type": "DeletedOwnComment", "data": Comment } | { "type": "DeletedCommentId", "data": number } | { "type": "DeletedOwnCommentId", "data": number } | { "type": "LastEventId", "data": number } | { "type": "CommentRejected", "data": CommentBodyError } | { "type": "RateLimited", "data": RateLimited } | { "type": "LoginRequired" } | { "type": "Batch", "data": Array<BackendMessage> };
@@ -0,0 +1,22 @@
+//! Writes the JSON Schema and the TypeScript definitions of the messages to
+//! `commons/schema`, or to the directory provided as argument.
+use std::path::PathBuf;
+
+use commons::schema::{json_schema, typescript, JSON_SCHEMA_FILE, SCHEMA_DIR, TYPESCRIPT_FILE};
+
+fn main() -> std::io::Result<()> {
+    let dir = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SCHEMA_DIR));
+    std::fs::create_dir_all(&dir)?;
+    for (file, content) in [
+        (JSON_SCHEMA_FILE, json_schema()),
+        (TYPESCRIPT_FILE, typescript()),
+    ] {
+        let path = dir.join(file);
+        std::fs::write(&path, content)?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,280 @@
+//! Wire formats of the websocket messages.
+//!
+//! The codec of a connection is negotiated with the websocket subprotocol, as
+//! described in [`crate::messages::protocol`].
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::messages::DecodeError;
+
+/// Error raised by a codec while encoding or decoding a message.
+pub type CodecError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Format in which the messages are sent over the websocket.
+pub trait Codec: 'static {
+    /// Name of the codec, as used in the subprotocol.
+    const NAME: &'static str;
+    /// Whether the messages are sent in text frames rather than binary ones.
+    const TEXT: bool = false;
+    /// Whether the messages are compressed, as negotiated with the
+    /// [`DEFLATE_SUFFIX`] of the subprotocol.
+    const COMPRESSED: bool = false;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError>;
+
+    /// Decodes a value, failing instead of allocating more than `limit` bytes.
+    fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, DecodeError>;
+}
+
+/// Checks the size of the encoded message, for the codecs whose allocations
+/// are bounded by the size of their input.
+fn check_size(bytes: &[u8], limit: u64) -> Result<(), DecodeError> {
+    if bytes.len() as u64 > limit {
+        Err(DecodeError::TooLarge(limit))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Compact binary format, the default one of the application.
+pub struct Bincode;
+
+impl Codec for Bincode {
+    const NAME: &'static str = "bincode";
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, DecodeError> {
+        use bincode::Options;
+
+        check_size(bytes, limit)?;
+        // Lengths are read from the message, so that the limit must also
+        // bound what they make bincode allocate.
+        bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(limit)
+            .deserialize(bytes)
+            .map_err(|err| match *err {
+                bincode::ErrorKind::SizeLimit => DecodeError::TooLarge(limit),
+                _ => DecodeError::Invalid(err),
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// JSON, readable in the browser devtools and usable from non-Rust clients.
+///
+/// The messages are represented with the name of their variant under `type`
+/// and its content, if any, under `data`, as documented in
+/// [`crate::messages`].
+pub struct Json;
+
+impl Codec for Json {
+    const NAME: &'static str = "json";
+    const TEXT: bool = true;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        let bytes = match tag(serde_json::to_value(value)?) {
+            Ok(tagged) => serde_json::to_vec(&tagged),
+            Err(value) => serde_json::to_vec(&value),
+        }?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, DecodeError> {
+        check_size(bytes, limit)?;
+        serde_json::from_slice(bytes)
+            .map(untag)
+            .and_then(serde_json::from_value)
+            .map_err(|err| DecodeError::Invalid(err.into()))
+    }
+}
+
+/// Message tagged with the name of its variant, as sent by the JSON codec.
+///
+/// The binary codecs do not support the tagged representations of serde, so
+/// that the messages are tagged by the JSON codec rather than by their derive.
+#[derive(Serialize)]
+pub(crate) struct Tagged {
+    #[serde(rename = "type")]
+    variant: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+/// Tags an externally tagged variant, such as `{"Login": "alice"}` or
+/// `"Logout"`, returning the other values as is.
+pub(crate) fn tag(value: Value) -> Result<Tagged, Value> {
+    match value {
+        Value::String(variant) => Ok(Tagged {
+            variant,
+            data: None,
+        }),
+        Value::Object(object) if object.len() == 1 => {
+            let (variant, data) = object.into_iter().next().unwrap();
+            Ok(Tagged {
+                variant,
+                data: Some(data),
+            })
+        }
+        value => Err(value),
+    }
+}
+
+/// Turns a variant tagged with `type` and `data` back into an externally tagged one.
+pub(crate) fn untag(value: Value) -> Value {
+    match value {
+        Value::Object(mut object)
+            if object.get("type").is_some_and(Value::is_string)
+                && object.keys().all(|key| key == "type" || key == "data") =>
+        {
+            let Some(Value::String(variant)) = object.remove("type") else {
+                unreachable!()
+            };
+            match object.remove("data") {
+                Some(data) => json!({ variant: data }),
+                None => Value::String(variant),
+            }
+        }
+        value => value,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// MessagePack, with the fields of the structs named.
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    const NAME: &'static str = "msgpack";
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, DecodeError> {
+        check_size(bytes, limit)?;
+        rmp_serde::from_slice(bytes).map_err(|err| DecodeError::Invalid(err.into()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// CBOR, as standardized in RFC 8949.
+pub struct Cbor;
+
+impl Codec for Cbor {
+    const NAME: &'static str = "cbor";
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, DecodeError> {
+        check_size(bytes, limit)?;
+        ciborium::from_reader(bytes).map_err(|err| DecodeError::Invalid(err.into()))
+    }
+}
+
+/// Suffix of the subprotocols of the codecs wrapped in [`Deflate`], such as
+/// `ayw.v7.json+deflate`.
+pub const DEFLATE_SUFFIX: &str = "+deflate";
+
+/// Size in bytes above which [`Deflate`] compresses the messages, as smaller
+/// ones barely shrink.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Header byte of the messages sent as is by [`Deflate`].
+const UNCOMPRESSED: u8 = 0;
+/// Header byte of the messages compressed by [`Deflate`].
+const DEFLATED: u8 = 1;
+
+/// Envelope compressing with deflate the messages of the codec `C` larger
+/// than [`COMPRESSION_THRESHOLD`].
+///
+/// The messages are sent in binary frames, prefixed with a byte telling
+/// whether they are compressed.
+#[derive(Debug, Clone, Copy)]
+pub struct Deflate<C>(PhantomData<C>);
+
+impl<C: Codec> Codec for Deflate<C> {
+    const NAME: &'static str = C::NAME;
+    const COMPRESSED: bool = true;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        let bytes = C::encode(value)?;
+        if bytes.len() <= COMPRESSION_THRESHOLD {
+            let mut message = Vec::with_capacity(bytes.len() + 1);
+            message.push(UNCOMPRESSED);
+            message.extend_from_slice(&bytes);
+            return Ok(message);
+        }
+        let mut encoder = DeflateEncoder::new(vec![DEFLATED], Compression::default());
+        encoder.write_all(&bytes)?;
+        Ok(encoder.finish()?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, DecodeError> {
+        check_size(bytes, limit)?;
+        match bytes.split_first() {
+            Some((&UNCOMPRESSED, bytes)) => C::decode(bytes, limit),
+            Some((&DEFLATED, bytes)) => {
+                // The decompressed message is bounded by the limit as well,
+                // reading one more byte to tell whether it exceeds it.
+                let mut decompressed = Vec::new();
+                DeflateDecoder::new(bytes)
+                    .take(limit.saturating_add(1))
+                    .read_to_end(&mut decompressed)
+                    .map_err(|err| DecodeError::Invalid(err.into()))?;
+                check_size(&decompressed, limit)?;
+                C::decode(&decompressed, limit)
+            }
+            Some((header, _)) => Err(DecodeError::Invalid(
+                format!("unknown compression header {}", header).into(),
+            )),
+            None => Err(DecodeError::Invalid("empty message".into())),
+        }
+    }
+}
+
+/// The available codecs, to choose one at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecKind {
+    Bincode,
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl CodecKind {
+    pub const ALL: [CodecKind; 4] = [
+        CodecKind::Bincode,
+        CodecKind::Json,
+        CodecKind::MessagePack,
+        CodecKind::Cbor,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CodecKind::Bincode => Bincode::NAME,
+            CodecKind::Json => Json::NAME,
+            CodecKind::MessagePack => MessagePack::NAME,
+            CodecKind::Cbor => Cbor::NAME,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
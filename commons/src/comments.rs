@@ -1,8 +1,112 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of characters of a comment body.
+pub const COMMENT_MAX_LENGTH: usize = 2000;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
 pub struct Comment {
     pub id: i32,
     pub user_id: i32,
     pub body: String,
-}
\ No newline at end of file
+}
+
+/// Reasons why a comment body is not acceptable.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
+pub enum CommentBodyError {
+    Empty,
+    TooLong,
+    ControlCharacter,
+}
+
+impl Display for CommentBodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommentBodyError::Empty => write!(f, "The comment cannot be empty."),
+            CommentBodyError::TooLong => write!(
+                f,
+                "The comment must be at most {} characters long.",
+                COMMENT_MAX_LENGTH
+            ),
+            CommentBodyError::ControlCharacter => {
+                write!(f, "The comment cannot contain control characters.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommentBodyError {}
+
+/// Returns the comment body trimmed of surrounding whitespace, if acceptable.
+///
+/// The trimmed body must not be empty, must be at most [`COMMENT_MAX_LENGTH`]
+/// characters long and must not contain control characters other than new
+/// lines and tabs.
+pub fn validate_body(body: &str) -> Result<String, CommentBodyError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(CommentBodyError::Empty);
+    }
+    if body.chars().count() > COMMENT_MAX_LENGTH {
+        return Err(CommentBodyError::TooLong);
+    }
+    if body
+        .chars()
+        .any(|character| character.is_control() && character != '\n' && character != '\t')
+    {
+        return Err(CommentBodyError::ControlCharacter);
+    }
+    Ok(body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_accepted_bodies() {
+        assert_eq!(validate_body("  Hello\n"), Ok("Hello".to_string()));
+        assert_eq!(
+            validate_body("First line\n\tSecond line"),
+            Ok("First line\n\tSecond line".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_empty_bodies() {
+        assert_eq!(validate_body(""), Err(CommentBodyError::Empty));
+        assert_eq!(validate_body(" \n\t "), Err(CommentBodyError::Empty));
+    }
+
+    #[test]
+    fn limits_the_length_of_the_bodies() {
+        let body = "a".repeat(COMMENT_MAX_LENGTH);
+        assert_eq!(validate_body(&body), Ok(body.clone()));
+        let body = "a".repeat(COMMENT_MAX_LENGTH + 1);
+        assert_eq!(validate_body(&body), Err(CommentBodyError::TooLong));
+    }
+
+    #[test]
+    fn counts_characters_rather_than_bytes() {
+        // Four bytes each, within the limit in characters but not in bytes.
+        let body = "🦀".repeat(COMMENT_MAX_LENGTH);
+        assert_eq!(validate_body(&body), Ok(body.clone()));
+        let body = "é".repeat(COMMENT_MAX_LENGTH + 1);
+        assert_eq!(validate_body(&body), Err(CommentBodyError::TooLong));
+    }
+
+    #[test]
+    fn rejects_control_characters() {
+        assert_eq!(
+            validate_body("Hello\u{0}world"),
+            Err(CommentBodyError::ControlCharacter)
+        );
+        assert_eq!(
+            validate_body("Hello\rworld"),
+            Err(CommentBodyError::ControlCharacter)
+        );
+    }
+}
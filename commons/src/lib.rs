@@ -1,6 +1,9 @@
+pub mod codecs;
 pub mod messages;
 pub mod users;
 pub mod comments;
+#[cfg(feature = "schema")]
+pub mod schema;
 
 pub mod prelude {
     pub use crate::messages::*;
@@ -1,97 +1,416 @@
 //! Module providing the websocket messages used in the application.
+//!
+//! # JSON representation
+//!
+//! With the JSON codec, the messages are sent in text frames, with the name of
+//! the variant under `type` and its content, if any, under `data`:
+//!
+//! ```json
+//! {"type": "Login", "data": "alice"}
+//! {"type": "Logout"}
+//! {"type": "InsertComment", "data": "Hello"}
+//! {"type": "Batch", "data": [{"type": "NewComment", "data": {"id": 1, "user_id": 1, "body": "Hello"}}]}
+//! ```
+//!
+//! This representation is stable within a [`PROTOCOL_VERSION`]. Clients not
+//! offering any subprotocol are spoken JSON at the current version, and
+//! closed as speaking an incompatible protocol when sending binary frames, as
+//! the clients preceding the versioning of the protocol do.
 use serde::{Deserialize, Serialize};
 
-use crate::prelude::{Comment, User};
+use crate::codecs::{Codec, CodecError, CodecKind, DEFLATE_SUFFIX};
+use crate::prelude::{Comment, CommentBodyError, Session, UsernameError};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Version of the messages exchanged over the websocket, to be increased
+/// whenever [`FrontendMessage`] or [`BackendMessage`] change their encoding,
+/// such as when adding, removing or reordering variants.
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/// Returns the subprotocol of the current [`PROTOCOL_VERSION`] without codec.
+fn protocol_prefix() -> String {
+    format!("ayw.v{}", PROTOCOL_VERSION)
+}
+
+/// Returns the websocket subprotocol of the current [`PROTOCOL_VERSION`] with
+/// the provided codec, such as `ayw.v7.json` or `ayw.v7.json+deflate`,
+/// negotiated with the `Sec-WebSocket-Protocol` header.
+pub fn protocol<C: Codec>() -> String {
+    let suffix = if C::COMPRESSED { DEFLATE_SUFFIX } else { "" };
+    format!("{}.{}{}", protocol_prefix(), C::NAME, suffix)
+}
+
+/// Returns the codec of a subprotocol of the current [`PROTOCOL_VERSION`], if
+/// any, and whether its messages are compressed.
+///
+/// The subprotocol without codec, used by the frontends predating the codecs,
+/// stands for bincode.
+pub fn parse_protocol(protocol: &str) -> Option<(CodecKind, bool)> {
+    let protocol = protocol.strip_prefix(&protocol_prefix())?;
+    let (codec, compressed) = match protocol.strip_suffix(DEFLATE_SUFFIX) {
+        Some(codec) => (codec, true),
+        None => (protocol, false),
+    };
+    match codec {
+        "" => Some((CodecKind::Bincode, compressed)),
+        codec => Some((CodecKind::from_name(codec.strip_prefix('.')?)?, compressed)),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
 pub struct CloseReason {
     code: u16,
     reason: Option<String>,
 }
 
+/// Prefix of the close reasons carrying the delay before reconnecting.
+const RETRY_AFTER_PREFIX: &str = "retry-after=";
+
+impl CloseReason {
+    /// Close code of a connection closed normally.
+    pub const NORMAL: u16 = 1000;
+    /// Close code of a connection closed because the server is going away.
+    pub const GOING_AWAY: u16 = 1001;
+    /// Close code of a connection closed because the peer broke the rules, such as the rate limits.
+    pub const POLICY_VIOLATION: u16 = 1008;
+    /// Close code of a connection closed because a message was too large.
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+    /// Close code of a connection closed because the client speaks another
+    /// version of the protocol, and needs to be reloaded.
+    pub const INCOMPATIBLE_PROTOCOL: u16 = 4000;
+
+    pub fn new(code: u16, reason: Option<String>) -> Self {
+        Self { code, reason }
+    }
+
+    /// Reason of a server shutting down, optionally hinting when to reconnect.
+    pub fn going_away(retry_after: Option<std::time::Duration>) -> Self {
+        Self {
+            code: Self::GOING_AWAY,
+            reason: retry_after
+                .map(|retry_after| format!("{}{}", RETRY_AFTER_PREFIX, retry_after.as_secs())),
+        }
+    }
+
+    /// Reason of a client exceeding the rate limits, asked to wait before
+    /// reconnecting.
+    pub fn policy_violation(retry_after: std::time::Duration) -> Self {
+        Self {
+            code: Self::POLICY_VIOLATION,
+            reason: Some(format!(
+                "{}{}",
+                RETRY_AFTER_PREFIX,
+                retry_after.as_secs_f64().ceil() as u64
+            )),
+        }
+    }
+
+    /// Reason of a client speaking another version of the protocol.
+    pub fn incompatible_protocol(offered: &str) -> Self {
+        Self {
+            code: Self::INCOMPATIBLE_PROTOCOL,
+            reason: Some(format!(
+                "incompatible protocol {}, expected {}.{{{}}}[{}]",
+                offered,
+                protocol_prefix(),
+                CodecKind::ALL.map(CodecKind::name).join(","),
+                DEFLATE_SUFFIX
+            )),
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// Returns the delay the peer asked to wait before reconnecting, if any.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        self.reason()?
+            .strip_prefix(RETRY_AFTER_PREFIX)?
+            .parse()
+            .ok()
+            .map(std::time::Duration::from_secs)
+    }
+}
+
+/// A message refused because too many messages of its kind were sent.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
+pub struct RateLimited {
+    /// Kind of the refused message, as returned by [`FrontendMessage::kind`].
+    pub kind: String,
+    /// Delay after which a message of the same kind is accepted again.
+    #[cfg_attr(feature = "schema", ts(type = "{ secs: number, nanos: number }"))]
+    pub retry_after: std::time::Duration,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many requests, please try again in {} seconds",
+            self.retry_after.as_secs_f64().ceil()
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
+// The JSON codec tags the messages, as documented above.
+#[cfg_attr(
+    feature = "schema",
+    schemars(tag = "type", content = "data"),
+    ts(tag = "type", content = "data")
+)]
 pub enum FrontendMessage {
     Close(Option<CloseReason>),
     Login(String),
-    InsertComment((User, String)),
+    /// Resumes the session identified by the provided token.
+    Resume(String),
+    /// Closes the current session, invalidating its token.
+    Logout,
+    /// Inserts a comment of the logged in user.
+    InsertComment(String),
+    /// Deletes a comment of the logged in user.
     DeleteComment(Comment)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
+// The JSON codec tags the messages, as documented above.
+#[cfg_attr(
+    feature = "schema",
+    schemars(tag = "type", content = "data"),
+    ts(tag = "type", content = "data")
+)]
 pub enum BackendMessage {
-    LoggedIn(User),
+    LoggedIn(Session),
+    /// The username provided to login is not acceptable.
+    LoginRejected(UsernameError),
+    /// The session the frontend tried to resume is not valid anymore.
+    SessionExpired,
     NewComment(Comment),
     UpdatedComment(Comment),
+    /// A comment of the logged in user was inserted.
     InsertedComment(Comment),
     Comments(Vec<Comment>),
     DeletedComment(Comment),
+    /// A comment of the logged in user was updated.
+    UpdatedOwnComment(Comment),
+    /// A comment of the logged in user was deleted.
+    DeletedOwnComment(Comment),
+    /// A comment too large to be notified was deleted, only its id being known.
+    DeletedCommentId(i32),
+    /// A comment of the logged in user too large to be notified was deleted,
+    /// or given to another user, only its id being known.
+    DeletedOwnCommentId(i32),
+    /// Id of the event the previous messages were sent for, when the backend
+    /// reads the events from its outbox. Reconnecting clients can resume
+    /// after the last one received with the `last_event_id` query parameter
+    /// of the websocket, the ids not being sent in increasing order.
+    LastEventId(#[cfg_attr(feature = "schema", ts(type = "number"))] i64),
+    /// The body of the comment to insert is not acceptable.
+    CommentRejected(CommentBodyError),
+    /// The message was not handled, as the rate limits were exceeded.
+    RateLimited(RateLimited),
+    /// The message was not handled, as it needs a logged in session.
+    LoginRequired,
+    /// Events that happened within a short window, sent in a single frame.
+    Batch(
+        #[serde(with = "tagged_messages")]
+        #[cfg_attr(
+            feature = "schema",
+            schemars(with = "Vec<BackendMessage>"),
+            ts(as = "Vec<BackendMessage>")
+        )]
+        Vec<BackendMessage>,
+    ),
 }
 
-#[cfg(feature = "backend")]
-impl actix::Message for BackendMessage {
-    type Result = ();
+/// Serialization of the messages of a batch, tagged in JSON as the messages
+/// sent alone are by the JSON codec.
+mod tagged_messages {
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    use super::BackendMessage;
+    use crate::codecs::{tag, untag};
+
+    pub fn serialize<S: Serializer>(
+        messages: &[BackendMessage],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return messages.serialize(serializer);
+        }
+        let messages = messages
+            .iter()
+            .map(|message| {
+                let value = serde_json::to_value(message).map_err(S::Error::custom)?;
+                tag(value).map_err(|value| S::Error::custom(format!("{} is not a message", value)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        serializer.collect_seq(messages)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<BackendMessage>, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Vec::deserialize(deserializer);
+        }
+        Vec::<Value>::deserialize(deserializer)?
+            .into_iter()
+            .map(|message| serde_json::from_value(untag(message)).map_err(D::Error::custom))
+            .collect()
+    }
 }
 
-#[cfg(feature = "backend")]
-impl From<BackendMessage> for bytes::Bytes {
-    fn from(msg: BackendMessage) -> Self {
-        bincode::serialize(&msg).unwrap().into()
+impl FrontendMessage {
+    /// Names of all the variants, as returned by [`FrontendMessage::kind`].
+    pub const KINDS: &'static [&'static str] = &[
+        "Close",
+        "Login",
+        "Resume",
+        "Logout",
+        "InsertComment",
+        "DeleteComment",
+    ];
+
+    /// Returns the name of the variant, for use in logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FrontendMessage::Close(_) => "Close",
+            FrontendMessage::Login(_) => "Login",
+            FrontendMessage::Resume(_) => "Resume",
+            FrontendMessage::Logout => "Logout",
+            FrontendMessage::InsertComment(_) => "InsertComment",
+            FrontendMessage::DeleteComment(_) => "DeleteComment",
+        }
+    }
+}
+
+impl BackendMessage {
+    /// Returns the name of the variant, for use in logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BackendMessage::LoggedIn(_) => "LoggedIn",
+            BackendMessage::LoginRejected(_) => "LoginRejected",
+            BackendMessage::SessionExpired => "SessionExpired",
+            BackendMessage::NewComment(_) => "NewComment",
+            BackendMessage::UpdatedComment(_) => "UpdatedComment",
+            BackendMessage::InsertedComment(_) => "InsertedComment",
+            BackendMessage::Comments(_) => "Comments",
+            BackendMessage::DeletedComment(_) => "DeletedComment",
+            BackendMessage::UpdatedOwnComment(_) => "UpdatedOwnComment",
+            BackendMessage::DeletedOwnComment(_) => "DeletedOwnComment",
+            BackendMessage::DeletedCommentId(_) => "DeletedCommentId",
+            BackendMessage::DeletedOwnCommentId(_) => "DeletedOwnCommentId",
+            BackendMessage::LastEventId(_) => "LastEventId",
+            BackendMessage::CommentRejected(_) => "CommentRejected",
+            BackendMessage::RateLimited(_) => "RateLimited",
+            BackendMessage::LoginRequired => "LoginRequired",
+            BackendMessage::Batch(_) => "Batch",
+        }
+    }
+}
+
+/// Errors raised when a websocket frame cannot be decoded into a message.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The frame is not of a kind that carries messages, such as a text frame.
+    UnexpectedFrame(&'static str),
+    /// The message is larger than the allowed size.
+    TooLarge(u64),
+    /// The content of the frame is not a valid message.
+    Invalid(CodecError),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedFrame(kind) => write!(f, "unexpected {} frame", kind),
+            DecodeError::TooLarge(limit) => write!(f, "message larger than {} bytes", limit),
+            DecodeError::Invalid(err) => write!(f, "invalid message: {}", err),
+        }
     }
 }
 
+impl std::error::Error for DecodeError {}
+
 #[cfg(feature = "backend")]
-impl From<actix_web_actors::ws::Message> for FrontendMessage {
-    fn from(actix_message: actix_web_actors::ws::Message) -> Self {
+impl actix::Message for BackendMessage {
+    type Result = ();
+}
+
+#[cfg(feature = "backend")]
+impl FrontendMessage {
+    /// Decodes the message carried by a websocket frame, refusing messages
+    /// larger than `limit` bytes.
+    ///
+    /// The continuation frames are to be assembled by the caller beforehand.
+    pub fn decode<C: Codec>(
+        actix_message: actix_web_actors::ws::Message,
+        limit: u64,
+    ) -> Result<Self, DecodeError> {
         match actix_message {
-            actix_web_actors::ws::Message::Text(text) => {
-                log::error!("Unexpected text message from backend: {:?}", text);
-                unreachable!("Unexpected text message from backend");
-            }
-            actix_web_actors::ws::Message::Binary(bin) => bincode::deserialize(&bin).unwrap(),
-            actix_web_actors::ws::Message::Ping(_) => {
-                log::error!("Unexpected ping message from backend");
-                unreachable!("Unexpected ping message from backend");
-            }
-            actix_web_actors::ws::Message::Pong(_) => {
-                log::error!("Unexpected pong message from backend");
-                unreachable!("Unexpected pong message from backend");
+            actix_web_actors::ws::Message::Text(text) if C::TEXT => {
+                C::decode(text.as_bytes(), limit)
             }
+            actix_web_actors::ws::Message::Text(_) => Err(DecodeError::UnexpectedFrame("text")),
+            actix_web_actors::ws::Message::Binary(bin) => C::decode(&bin, limit),
+            actix_web_actors::ws::Message::Ping(_) => Err(DecodeError::UnexpectedFrame("ping")),
+            actix_web_actors::ws::Message::Pong(_) => Err(DecodeError::UnexpectedFrame("pong")),
             actix_web_actors::ws::Message::Close(reason) => {
-                FrontendMessage::Close(reason.map(|r: actix_web_actors::ws::CloseReason| {
-                    CloseReason {
-                        code: r.code.into(),
-                        reason: r.description,
-                    }
-                }))
+                Ok(FrontendMessage::Close(reason.map(CloseReason::from)))
             }
             actix_web_actors::ws::Message::Continuation(_) => {
-                log::error!("Unexpected continuation message from backend");
-                unreachable!("Unexpected continuation message from backend");
-            }
-            actix_web_actors::ws::Message::Nop => {
-                log::error!("Unexpected nop message from backend");
-                unreachable!("Unexpected nop message from backend");
+                Err(DecodeError::UnexpectedFrame("continuation"))
             }
+            actix_web_actors::ws::Message::Nop => Err(DecodeError::UnexpectedFrame("nop")),
         }
     }
 }
 
-#[cfg(feature = "frontend")]
-impl From<gloo_net::websocket::Message> for BackendMessage {
-    fn from(msg: gloo_net::websocket::Message) -> Self {
-        match msg {
-            gloo_net::websocket::Message::Text(text) => {
-                log::error!("Unexpected text message from frontend: {:?}", text);
-                unreachable!("Unexpected text message from frontend");
-            }
-            gloo_net::websocket::Message::Bytes(bin) => bincode::deserialize(&bin).unwrap(),
+#[cfg(feature = "backend")]
+impl From<actix_web_actors::ws::CloseReason> for CloseReason {
+    fn from(reason: actix_web_actors::ws::CloseReason) -> Self {
+        Self::new(reason.code.into(), reason.description)
+    }
+}
+
+#[cfg(feature = "backend")]
+impl From<CloseReason> for actix_web_actors::ws::CloseReason {
+    fn from(reason: CloseReason) -> Self {
+        Self {
+            code: reason.code.into(),
+            description: reason.reason,
         }
     }
 }
 
 #[cfg(feature = "frontend")]
-impl From<FrontendMessage> for gloo_net::websocket::Message {
-    fn from(msg: FrontendMessage) -> Self {
-        gloo_net::websocket::Message::Bytes(bincode::serialize(&msg).unwrap())
+impl From<gloo_net::websocket::events::CloseEvent> for CloseReason {
+    fn from(event: gloo_net::websocket::events::CloseEvent) -> Self {
+        let reason = Some(event.reason).filter(|reason| !reason.is_empty());
+        Self::new(event.code, reason)
+    }
+}
+
+/// Unpacks a batch into its messages, the other messages standing alone.
+impl From<BackendMessage> for Vec<BackendMessage> {
+    fn from(message: BackendMessage) -> Self {
+        match message {
+            BackendMessage::Batch(messages) => messages,
+            message => vec![message],
+        }
     }
 }
@@ -0,0 +1,56 @@
+//! JSON Schema and TypeScript definitions of the messages, in the
+//! representation of the [`Json`](crate::codecs::Json) codec.
+//!
+//! The definitions are checked in under `commons/schema`, and regenerated with
+//! `cargo run -p commons --features schema --bin protocol-schema`.
+use schemars::generate::SchemaSettings;
+use schemars::JsonSchema;
+use ts_rs::TS;
+
+use crate::prelude::*;
+
+/// Directory of the checked-in definitions, relative to the `commons` crate.
+pub const SCHEMA_DIR: &str = "schema";
+/// Name of the JSON Schema file in [`SCHEMA_DIR`].
+pub const JSON_SCHEMA_FILE: &str = "messages.schema.json";
+/// Name of the TypeScript definitions file in [`SCHEMA_DIR`].
+pub const TYPESCRIPT_FILE: &str = "messages.ts";
+
+/// Returns the JSON Schema of the messages, with a definition per type.
+pub fn json_schema() -> String {
+    let mut generator = SchemaSettings::draft2020_12().into_generator();
+    fn define<T: JsonSchema>(generator: &mut schemars::SchemaGenerator) {
+        generator.subschema_for::<T>();
+    }
+    define::<FrontendMessage>(&mut generator);
+    define::<BackendMessage>(&mut generator);
+    let schema = serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": format!("ayw.v{} messages", PROTOCOL_VERSION),
+        "$defs": generator.take_definitions(true),
+    });
+    serde_json::to_string_pretty(&schema).expect("serializable schema") + "\n"
+}
+
+/// Returns the TypeScript definitions of the messages and of the types they carry.
+pub fn typescript() -> String {
+    fn declare<T: TS>() -> String {
+        format!("{}export {}\n", T::docs().unwrap_or_default(), T::decl())
+    }
+    let declarations = [
+        declare::<User>(),
+        declare::<Session>(),
+        declare::<UsernameError>(),
+        declare::<Comment>(),
+        declare::<CommentBodyError>(),
+        declare::<CloseReason>(),
+        declare::<RateLimited>(),
+        declare::<FrontendMessage>(),
+        declare::<BackendMessage>(),
+    ];
+    format!(
+        "// Messages of the ayw.v{} protocol, generated by `protocol-schema`.\n\n{}",
+        PROTOCOL_VERSION,
+        declarations.join("\n")
+    )
+}
@@ -1,7 +1,168 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
+/// Minimum number of characters of a username.
+pub const USERNAME_MIN_LENGTH: usize = 3;
+/// Maximum number of characters of a username.
+pub const USERNAME_MAX_LENGTH: usize = 32;
+/// Usernames that cannot be registered, compared case-insensitively.
+pub const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "anonymous",
+    "moderator",
+    "null",
+    "root",
+    "support",
+    "system",
+    "undefined",
+];
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
 pub struct User {
     pub id: i32,
     pub username: String,
 }
+
+/// A logged-in user, alongside the token identifying the session.
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
+pub struct Session {
+    pub user: User,
+    pub token: String,
+}
+
+// The token is a bearer secret, kept out of the logs of the messages.
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("user", &self.user)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Reasons why a username is not acceptable.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema, ts_rs::TS))]
+pub enum UsernameError {
+    TooShort,
+    TooLong,
+    InvalidCharacter(char),
+    Reserved,
+}
+
+impl Display for UsernameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsernameError::TooShort => write!(
+                f,
+                "The username must be at least {} characters long.",
+                USERNAME_MIN_LENGTH
+            ),
+            UsernameError::TooLong => write!(
+                f,
+                "The username must be at most {} characters long.",
+                USERNAME_MAX_LENGTH
+            ),
+            UsernameError::InvalidCharacter(character) => write!(
+                f,
+                "The username cannot contain '{}': only letters, digits, '_', '-' and '.' are allowed.",
+                character
+            ),
+            UsernameError::Reserved => write!(f, "This username is reserved."),
+        }
+    }
+}
+
+impl std::error::Error for UsernameError {}
+
+/// Checks that the provided username may be registered.
+///
+/// Usernames are made of ASCII letters, digits, `_`, `-` and `.`, are between
+/// [`USERNAME_MIN_LENGTH`] and [`USERNAME_MAX_LENGTH`] characters long, and
+/// cannot be one of the [`RESERVED_USERNAMES`].
+pub fn validate_username(username: &str) -> Result<(), UsernameError> {
+    let length = username.chars().count();
+    if length < USERNAME_MIN_LENGTH {
+        return Err(UsernameError::TooShort);
+    }
+    if length > USERNAME_MAX_LENGTH {
+        return Err(UsernameError::TooLong);
+    }
+    if let Some(character) = username
+        .chars()
+        .find(|character| !(character.is_ascii_alphanumeric() || "_-.".contains(*character)))
+    {
+        return Err(UsernameError::InvalidCharacter(character));
+    }
+    if RESERVED_USERNAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(username))
+    {
+        return Err(UsernameError::Reserved);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_usernames() {
+        assert_eq!(validate_username("bob"), Ok(()));
+        assert_eq!(validate_username("Alice_42.dev-test"), Ok(()));
+        assert_eq!(validate_username(&"a".repeat(USERNAME_MAX_LENGTH)), Ok(()));
+    }
+
+    #[test]
+    fn rejects_short_usernames() {
+        assert_eq!(validate_username(""), Err(UsernameError::TooShort));
+        assert_eq!(validate_username("ab"), Err(UsernameError::TooShort));
+    }
+
+    #[test]
+    fn rejects_long_usernames() {
+        let username = "a".repeat(USERNAME_MAX_LENGTH + 1);
+        assert_eq!(validate_username(&username), Err(UsernameError::TooLong));
+    }
+
+    #[test]
+    fn counts_characters_rather_than_bytes() {
+        // Two bytes each, within the limit in characters but not in bytes.
+        let username = "é".repeat(USERNAME_MAX_LENGTH);
+        assert_eq!(
+            validate_username(&username),
+            Err(UsernameError::InvalidCharacter('é'))
+        );
+        let username = "é".repeat(USERNAME_MAX_LENGTH + 1);
+        assert_eq!(validate_username(&username), Err(UsernameError::TooLong));
+    }
+
+    #[test]
+    fn rejects_invalid_characters() {
+        assert_eq!(
+            validate_username("bob smith"),
+            Err(UsernameError::InvalidCharacter(' '))
+        );
+        assert_eq!(
+            validate_username("bob@home"),
+            Err(UsernameError::InvalidCharacter('@'))
+        );
+        assert_eq!(
+            validate_username("zoë"),
+            Err(UsernameError::InvalidCharacter('ë'))
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_usernames_in_any_case() {
+        assert_eq!(validate_username("admin"), Err(UsernameError::Reserved));
+        assert_eq!(validate_username("Admin"), Err(UsernameError::Reserved));
+        assert_eq!(validate_username("ROOT"), Err(UsernameError::Reserved));
+        assert_eq!(validate_username("admins"), Ok(()));
+    }
+}
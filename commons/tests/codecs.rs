@@ -0,0 +1,135 @@
+//! Checks that the codecs decode the messages they encode, and that they
+//! refuse the messages larger than the limit.
+use std::io::Write;
+
+use commons::codecs::{Bincode, Cbor, Codec, Deflate, Json, MessagePack};
+use commons::comments::Comment;
+use commons::messages::{BackendMessage, DecodeError, FrontendMessage};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+
+const LIMIT: u64 = 64 * 1024;
+
+fn comment(id: i32) -> Comment {
+    Comment {
+        id,
+        user_id: 1,
+        body: format!("Comment {}", id),
+    }
+}
+
+fn backend_messages() -> Vec<BackendMessage> {
+    vec![
+        BackendMessage::NewComment(comment(1)),
+        BackendMessage::LoginRequired,
+        BackendMessage::LastEventId(42),
+        // Large enough to be compressed by Deflate.
+        BackendMessage::Comments((0..100).map(comment).collect()),
+        BackendMessage::Batch(vec![
+            BackendMessage::NewComment(comment(2)),
+            BackendMessage::DeletedCommentId(1),
+            BackendMessage::Batch(vec![BackendMessage::LoginRequired]),
+        ]),
+    ]
+}
+
+fn frontend_messages() -> Vec<FrontendMessage> {
+    vec![
+        FrontendMessage::Login("alice".to_string()),
+        FrontendMessage::Logout,
+        FrontendMessage::InsertComment("Hello".to_string()),
+        FrontendMessage::DeleteComment(comment(1)),
+    ]
+}
+
+/// Checks the round trip of the messages, compared by their debug
+/// representation as they do not implement `PartialEq`.
+fn assert_round_trip<C: Codec>() {
+    for message in backend_messages() {
+        let bytes = C::encode(&message).unwrap();
+        let decoded: BackendMessage = C::decode(&bytes, LIMIT).unwrap();
+        assert_eq!(
+            format!("{:?}", decoded),
+            format!("{:?}", message),
+            "{}",
+            C::NAME
+        );
+    }
+    for message in frontend_messages() {
+        let bytes = C::encode(&message).unwrap();
+        let decoded: FrontendMessage = C::decode(&bytes, LIMIT).unwrap();
+        assert_eq!(
+            format!("{:?}", decoded),
+            format!("{:?}", message),
+            "{}",
+            C::NAME
+        );
+    }
+}
+
+#[test]
+fn codecs_round_trip() {
+    assert_round_trip::<Bincode>();
+    assert_round_trip::<Json>();
+    assert_round_trip::<MessagePack>();
+    assert_round_trip::<Cbor>();
+    assert_round_trip::<Deflate<Bincode>>();
+    assert_round_trip::<Deflate<Json>>();
+    assert_round_trip::<Deflate<MessagePack>>();
+    assert_round_trip::<Deflate<Cbor>>();
+}
+
+#[test]
+fn json_tags_nested_batches() {
+    let message = BackendMessage::Batch(vec![
+        BackendMessage::NewComment(comment(1)),
+        BackendMessage::Batch(vec![BackendMessage::LoginRequired]),
+    ]);
+    let encoded: Value = serde_json::from_slice(&Json::encode(&message).unwrap()).unwrap();
+    assert_eq!(
+        encoded,
+        json!({
+            "type": "Batch",
+            "data": [
+                {"type": "NewComment", "data": {"id": 1, "user_id": 1, "body": "Comment 1"}},
+                {"type": "Batch", "data": [{"type": "LoginRequired"}]},
+            ],
+        })
+    );
+}
+
+fn assert_too_large<C: Codec>(bytes: &[u8], limit: u64) {
+    match C::decode::<BackendMessage>(bytes, limit) {
+        Err(DecodeError::TooLarge(too_large)) => assert_eq!(too_large, limit, "{}", C::NAME),
+        result => panic!(
+            "{} decoded {:?}",
+            C::NAME,
+            result.map_err(|err| err.to_string())
+        ),
+    }
+}
+
+#[test]
+fn oversized_messages_are_too_large() {
+    let message = BackendMessage::Comments((0..100).map(comment).collect());
+    fn check<C: Codec>(message: &BackendMessage) {
+        let bytes = C::encode(message).unwrap();
+        assert_too_large::<C>(&bytes, bytes.len() as u64 - 1);
+    }
+    check::<Bincode>(&message);
+    check::<Json>(&message);
+    check::<MessagePack>(&message);
+    check::<Cbor>(&message);
+    check::<Deflate<Json>>(&message);
+}
+
+#[test]
+fn deflate_bombs_are_too_large() {
+    // Megabytes of whitespace compress to a few kilobytes, within the limit.
+    let mut encoder = DeflateEncoder::new(vec![1], Compression::best());
+    encoder.write_all(&vec![b' '; 16 * 1024 * 1024]).unwrap();
+    let bomb = encoder.finish().unwrap();
+    assert!(bomb.len() as u64 <= LIMIT);
+    assert_too_large::<Deflate<Json>>(&bomb, LIMIT);
+}
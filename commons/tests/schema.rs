@@ -0,0 +1,27 @@
+//! Checks that the checked-in definitions of the messages match the Rust types.
+use std::path::Path;
+
+use commons::schema::{json_schema, typescript, JSON_SCHEMA_FILE, SCHEMA_DIR, TYPESCRIPT_FILE};
+
+fn assert_up_to_date(file: &str, generated: String) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(SCHEMA_DIR)
+        .join(file);
+    let checked_in = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        checked_in == generated,
+        "{} is out of date, regenerate it with \
+         `cargo run -p commons --features schema --bin protocol-schema`",
+        path.display()
+    );
+}
+
+#[test]
+fn json_schema_is_up_to_date() {
+    assert_up_to_date(JSON_SCHEMA_FILE, json_schema());
+}
+
+#[test]
+fn typescript_is_up_to_date() {
+    assert_up_to_date(TYPESCRIPT_FILE, typescript());
+}
@@ -1,9 +1,10 @@
-use yew_agent::Registrable;
+use commons::codecs::{Bincode, Deflate};
+use commons::messages::{BackendMessage, FrontendMessage};
 use frontend::worker::WebsocketWorker;
-use commons::messages::{FrontendMessage, BackendMessage};
+use yew_agent::Registrable;
 
 fn main() {
     wasm_logger::init(wasm_logger::Config::default());
 
-    WebsocketWorker::<FrontendMessage, BackendMessage>::registrar().register();
-}
\ No newline at end of file
+    WebsocketWorker::<FrontendMessage, BackendMessage, Deflate<Bincode>>::registrar().register();
+}
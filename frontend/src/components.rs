@@ -2,8 +2,8 @@
 
 pub mod app;
 pub mod login_form;
-pub use login_form::LoginForm;
 pub use app::App;
+pub use login_form::LoginForm;
 pub mod error_page;
 pub use error_page::ErrorPage;
 pub mod comment_popup;
@@ -11,4 +11,8 @@ pub use comment_popup::CommentPopup;
 pub mod comment;
 pub use comment::Comment;
 pub mod comments_dashboard;
-pub use comments_dashboard::CommentsDashboard;
\ No newline at end of file
+pub use comments_dashboard::CommentsDashboard;
+pub mod session_manager;
+pub use session_manager::SessionManager;
+pub mod protocol_banner;
+pub use protocol_banner::ProtocolBanner;
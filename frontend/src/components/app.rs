@@ -1,8 +1,7 @@
+use crate::components::{ProtocolBanner, SessionManager};
+use crate::contexts::WebsocketProvider;
 use crate::router::{switch, AppRoute};
-use crate::worker::*;
-use commons::messages::{BackendMessage, FrontendMessage};
 use yew::prelude::*;
-use yew_agent::worker::WorkerProvider;
 use yew_router::prelude::*;
 
 #[function_component]
@@ -11,9 +10,11 @@ pub fn App() -> Html {
     html! {
         <BrowserRouter>
             <div class="fullscreen_center_app">
-                <WorkerProvider<WebsocketWorker<FrontendMessage, BackendMessage>> path="web_socket_worker.js">
-                <Switch<AppRoute> render={switch} />
-            </WorkerProvider<WebsocketWorker<FrontendMessage, BackendMessage>>>
+                <WebsocketProvider>
+                    <ProtocolBanner />
+                    <SessionManager />
+                    <Switch<AppRoute> render={switch} />
+                </WebsocketProvider>
             </div>
         </BrowserRouter>
     }
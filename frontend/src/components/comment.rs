@@ -1,18 +1,16 @@
-use crate::worker::*;
-use commons::messages::{BackendMessage, FrontendMessage};
-use yew::prelude::*;
-use yew_agent::prelude::*;
+use crate::contexts::WebsocketContext;
+use commons::messages::FrontendMessage;
 use gloo::timers::callback::Timeout;
+use yew::prelude::*;
 
 pub struct Comment {
-    websocket: WorkerBridgeHandle<WebsocketWorker<FrontendMessage, BackendMessage>>,
-    deleting: Option<Timeout>
+    websocket: WebsocketContext,
+    deleting: Option<Timeout>,
 }
 
 #[derive(Debug, Clone)]
 pub enum WebsocketMessages {
     Frontend(FrontendMessage),
-    Backend(BackendMessage),
     StartDeleteComment(commons::comments::Comment),
     DeleteComment(commons::comments::Comment),
 }
@@ -28,21 +26,20 @@ impl Component for Comment {
     type Properties = CommentProps;
 
     fn create(ctx: &Context<Self>) -> Self {
+        let (websocket, _) = ctx
+            .link()
+            .context::<WebsocketContext>(Callback::noop())
+            .expect("WebsocketProvider is missing");
+
         Self {
-            websocket: ctx.link().bridge_worker(Callback::from({
-                let link = ctx.link().clone();
-                move |message: BackendMessage| {
-                    link.send_message(WebsocketMessages::Backend(message));
-                }
-            })),
-            deleting: None
+            websocket,
+            deleting: None,
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            WebsocketMessages::Frontend(fm) => self.websocket.send(fm.into()),
-            WebsocketMessages::Backend(_bm) => {}
+            WebsocketMessages::Frontend(fm) => self.websocket.send(fm),
             WebsocketMessages::StartDeleteComment(comment) => {
                 let link = ctx.link().clone();
                 self.deleting = Some(Timeout::new(1000, move || {
@@ -1,14 +1,14 @@
-use crate::worker::*;
+use crate::contexts::{BackendSubscription, WebsocketContext};
 use commons::{
     comments::Comment,
     messages::{BackendMessage, FrontendMessage},
 };
 use gloo::timers::callback::Timeout;
 use yew::prelude::*;
-use yew_agent::prelude::*;
 
 pub struct CommentPopup {
-    websocket: WorkerBridgeHandle<WebsocketWorker<FrontendMessage, BackendMessage>>,
+    websocket: WebsocketContext,
+    _subscription: BackendSubscription,
     comment: Option<Comment>,
     hiding: Option<Timeout>,
     before_hiding: Option<Timeout>,
@@ -27,13 +27,14 @@ impl Component for CommentPopup {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
+        let (websocket, _) = ctx
+            .link()
+            .context::<WebsocketContext>(Callback::noop())
+            .expect("WebsocketProvider is missing");
+
         Self {
-            websocket: ctx.link().bridge_worker(Callback::from({
-                let link = ctx.link().clone();
-                move |message: BackendMessage| {
-                    link.send_message(WebsocketMessages::Backend(message));
-                }
-            })),
+            _subscription: websocket.subscribe(ctx.link().callback(WebsocketMessages::Backend)),
+            websocket,
             comment: None,
             hiding: None,
             before_hiding: None,
@@ -42,7 +43,7 @@ impl Component for CommentPopup {
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            WebsocketMessages::Frontend(fm) => self.websocket.send(fm.into()),
+            WebsocketMessages::Frontend(fm) => self.websocket.send(fm),
             WebsocketMessages::Backend(bm) => match bm {
                 BackendMessage::InsertedComment(comment) => {
                     log::info!("Inserted comment: {:?}", comment);
@@ -1,20 +1,27 @@
-use crate::worker::*;
+use crate::components::Comment;
+use crate::components::CommentPopup;
+use crate::contexts::{BackendSubscription, WebsocketContext};
+use crate::stores::UserState;
+use commons::comments::{validate_body, COMMENT_MAX_LENGTH};
 use commons::messages::{BackendMessage, FrontendMessage};
-use yew::prelude::*;
-use yew_agent::prelude::*;
 use wasm_bindgen::JsCast;
-use crate::components::CommentPopup;
-use crate::components::Comment;
+use yew::prelude::*;
+use yewdux::prelude::*;
 
 pub struct CommentsDashboard {
-    websocket: WorkerBridgeHandle<WebsocketWorker<FrontendMessage, BackendMessage>>,
+    websocket: WebsocketContext,
+    _subscription: BackendSubscription,
     comments: Vec<commons::comments::Comment>,
+    /// Why the last comment was not posted.
+    error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum WebsocketMessages {
     Frontend(FrontendMessage),
     Backend(BackendMessage),
+    Submit(String),
+    Logout,
 }
 
 #[derive(Debug, Clone, PartialEq, Properties)]
@@ -27,20 +34,22 @@ impl Component for CommentsDashboard {
     type Properties = CommentsDashboardProps;
 
     fn create(ctx: &Context<Self>) -> Self {
+        let (websocket, _) = ctx
+            .link()
+            .context::<WebsocketContext>(Callback::noop())
+            .expect("WebsocketProvider is missing");
+
         Self {
-            websocket: ctx.link().bridge_worker(Callback::from({
-                let link = ctx.link().clone();
-                move |message: BackendMessage| {
-                    link.send_message(WebsocketMessages::Backend(message));
-                }
-            })),
+            _subscription: websocket.subscribe(ctx.link().callback(WebsocketMessages::Backend)),
+            websocket,
             comments: Vec::new(),
+            error: None,
         }
     }
 
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            WebsocketMessages::Frontend(fm) => self.websocket.send(fm.into()),
+            WebsocketMessages::Frontend(fm) => self.websocket.send(fm),
             WebsocketMessages::Backend(bm) => match bm {
                 BackendMessage::NewComment(comment) => {
                     log::info!("New comment: {:?}", comment);
@@ -50,14 +59,42 @@ impl Component for CommentsDashboard {
                     log::info!("Deleted comment: {:?}", comment);
                     self.comments.retain(|c| c.id != comment.id);
                 }
+                BackendMessage::DeletedCommentId(id) => {
+                    log::info!("Deleted comment: {}", id);
+                    self.comments.retain(|c| c.id != id);
+                }
+                BackendMessage::CommentRejected(error) => {
+                    self.error = Some(error.to_string());
+                }
+                BackendMessage::RateLimited(rate_limited)
+                    if rate_limited.kind == "InsertComment" =>
+                {
+                    self.error = Some(rate_limited.to_string());
+                }
+                BackendMessage::LoginRequired => {
+                    self.error = Some("Your session was lost, please log in again".to_string());
+                }
                 _ => {}
             },
+            WebsocketMessages::Submit(comment) => match validate_body(&comment) {
+                Ok(body) => {
+                    self.error = None;
+                    self.websocket.send(FrontendMessage::InsertComment(body));
+                }
+                Err(error) => {
+                    self.error = Some(error.to_string());
+                }
+            },
+            WebsocketMessages::Logout => {
+                // The session manager takes care of notifying the backend,
+                // and the comments page redirects to the login page.
+                Dispatch::<UserState>::global().reduce_mut(UserState::logout);
+            }
         }
         true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
-        let user = ctx.props().user.clone();
         let on_submit_comment = ctx.link().callback(move |event: SubmitEvent| {
             event.prevent_default();
             let comment = event
@@ -71,9 +108,18 @@ impl Component for CommentsDashboard {
                 .dyn_into::<web_sys::HtmlInputElement>()
                 .unwrap()
                 .value();
-            WebsocketMessages::Frontend(FrontendMessage::InsertComment((user.clone(), comment)))
+            WebsocketMessages::Submit(comment)
         });
 
+        let error = match &self.error {
+            Some(error) => html! {
+                <p class="error">{error}</p>
+            },
+            None => html! {},
+        };
+
+        let on_logout = ctx.link().callback(|_| WebsocketMessages::Logout);
+
         let comments = self
             .comments
             .iter()
@@ -87,9 +133,16 @@ impl Component for CommentsDashboard {
         html! {
             <div class="comments-dashboard">
                 <CommentPopup/>
+                <button class="logout" onclick={on_logout}>{"Logout"}</button>
                 <form method="POST" onsubmit={on_submit_comment}>
-                    <input name="comment" placeholder="Write a comment..." required=true />
+                    <input
+                        name="comment"
+                        placeholder="Write a comment..."
+                        required=true
+                        maxlength={COMMENT_MAX_LENGTH.to_string()}
+                    />
                     <button type="submit">{"Post comment"}</button>
+                    {error}
                 </form>
                 <ul class="comments">
                     {comments}
@@ -1,24 +1,23 @@
-use crate::stores::UserState;
-use crate::worker::*;
+use crate::contexts::{BackendSubscription, WebsocketContext};
 use commons::messages::{BackendMessage, FrontendMessage};
-use std::rc::Rc;
+use commons::users::{validate_username, USERNAME_MAX_LENGTH, USERNAME_MIN_LENGTH};
 use wasm_bindgen::JsCast;
-use web_sys::FormData;
 use yew::prelude::*;
-use yew_agent::prelude::*;
-use yewdux::prelude::*;
 
+/// Form sending the login request, whose outcome is handled by the
+/// [`SessionManager`](crate::components::SessionManager).
 pub struct LoginForm {
-    websocket: WorkerBridgeHandle<WebsocketWorker<FrontendMessage, BackendMessage>>,
-    user_state: Rc<UserState>,
-    dispatch: Dispatch<UserState>,
+    websocket: WebsocketContext,
+    _subscription: BackendSubscription,
+    /// Why the last login was not attempted or was refused.
+    error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum WebsocketMessages {
     Frontend(FrontendMessage),
     Backend(BackendMessage),
-    State(Rc<UserState>),
+    Submit(String),
 }
 
 impl Component for LoginForm {
@@ -26,35 +25,39 @@ impl Component for LoginForm {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        let dispatch = Dispatch::<UserState>::global()
-            .subscribe(ctx.link().callback(WebsocketMessages::State));
+        let (websocket, _) = ctx
+            .link()
+            .context::<WebsocketContext>(Callback::noop())
+            .expect("WebsocketProvider is missing");
 
         Self {
-            websocket: ctx.link().bridge_worker(Callback::from({
-                let link = ctx.link().clone();
-                move |message: BackendMessage| {
-                    link.send_message(WebsocketMessages::Backend(message));
-                }
-            })),
-            user_state: dispatch.get(),
-            dispatch,
+            _subscription: websocket.subscribe(ctx.link().callback(WebsocketMessages::Backend)),
+            websocket,
+            error: None,
         }
     }
 
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            WebsocketMessages::Frontend(fm) => self.websocket.send(fm.into()),
+            WebsocketMessages::Frontend(fm) => self.websocket.send(fm),
             WebsocketMessages::Backend(bm) => match bm {
-                BackendMessage::LoggedIn(user) => {
-                    log::info!("User logged in: {:?}", user);
-                    self.dispatch.reduce_mut(|state| {
-                        state.user = Some(user);
-                    });
+                BackendMessage::LoginRejected(error) => {
+                    self.error = Some(error.to_string());
+                }
+                BackendMessage::RateLimited(rate_limited) if rate_limited.kind == "Login" => {
+                    self.error = Some(rate_limited.to_string());
                 }
-                _ => {}
+                _ => return false,
             },
-            WebsocketMessages::State(state) => {
-                self.user_state = state;
+            WebsocketMessages::Submit(user_name) => {
+                // The backend runs the same validation, we only run it here
+                // as well to avoid a round-trip for invalid usernames.
+                self.error = validate_username(&user_name)
+                    .err()
+                    .map(|error| error.to_string());
+                if self.error.is_none() {
+                    self.websocket.send(FrontendMessage::Login(user_name));
+                }
             }
         }
         true
@@ -72,13 +75,26 @@ impl Component for LoginForm {
                 .unwrap()
                 .unchecked_into::<web_sys::HtmlInputElement>()
                 .value();
-            WebsocketMessages::Frontend(FrontendMessage::Login(user_name))
+            WebsocketMessages::Submit(user_name.trim().to_string())
         });
 
+        let error = match &self.error {
+            Some(error) => html! {
+                <p class="error">{error}</p>
+            },
+            None => html! {},
+        };
+
         html! {
             <form method="POST" onsubmit={on_submit}>
-                <input type="text" placeholder="Username" />
+                <input
+                    type="text"
+                    placeholder="Username"
+                    minlength={USERNAME_MIN_LENGTH.to_string()}
+                    maxlength={USERNAME_MAX_LENGTH.to_string()}
+                />
                 <button type="submit">{"Login"}</button>
+                {error}
             </form>
         }
     }
@@ -0,0 +1,46 @@
+//! Banner asking to reload the page when the backend speaks another version of the protocol.
+
+use crate::contexts::use_websocket_output;
+use crate::worker::WorkerOutput;
+use commons::messages::CloseReason;
+use yew::prelude::*;
+
+#[function_component(ProtocolBanner)]
+pub fn protocol_banner() -> Html {
+    let closed = use_websocket_output(|output| match output {
+        WorkerOutput::Closed(reason) if reason.code() == CloseReason::INCOMPATIBLE_PROTOCOL => {
+            Some(reason)
+        }
+        _ => None,
+    });
+
+    // The banner stays once the backend rejected the protocol.
+    let incompatible = use_state(|| None);
+    {
+        let incompatible = incompatible.clone();
+        use_effect(move || {
+            if let Some(reason) = closed.into_iter().last() {
+                incompatible.set(Some(reason));
+            }
+        });
+    }
+
+    let on_reload = Callback::from(|_: MouseEvent| {
+        if let Err(err) = gloo::utils::window().location().reload() {
+            log::error!("Error reloading the page: {:?}", err);
+        }
+    });
+
+    match &*incompatible {
+        Some(reason) => {
+            log::warn!("Incompatible protocol: {:?}", reason.reason());
+            html! {
+                <div class="protocol-banner">
+                    <p>{"A new version of the application is available, please reload the page."}</p>
+                    <button onclick={on_reload}>{"Reload"}</button>
+                </div>
+            }
+        }
+        None => html! {},
+    }
+}
@@ -0,0 +1,125 @@
+//! Component keeping the stored session in sync with the backend.
+
+use crate::contexts::{use_backend_messages, use_websocket, use_websocket_send};
+use crate::stores::UserState;
+use commons::messages::{BackendMessage, FrontendMessage};
+use gloo::timers::callback::Timeout;
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+#[function_component(SessionManager)]
+pub fn session_manager() -> Html {
+    let (user_state, dispatch) = use_store::<UserState>();
+    let send = use_websocket_send();
+
+    // The outcomes of the logins and session resumptions since the previous render.
+    let sessions = use_backend_messages(|message| match message {
+        BackendMessage::LoggedIn(session) => Some(Some(session)),
+        BackendMessage::SessionExpired => Some(None),
+        _ => None,
+    });
+
+    // The delays after which the refused session resumptions are accepted,
+    // such as when many frontends reconnect at once.
+    let resume_retry_afters = use_backend_messages(|message| match message {
+        BackendMessage::RateLimited(rate_limited) if rate_limited.kind == "Resume" => {
+            Some(rate_limited.retry_after)
+        }
+        _ => None,
+    });
+
+    // The session stored from a previous visit may have been invalidated in
+    // the meantime, so we revalidate it with the backend upon loading.
+    {
+        let send = send.clone();
+        let token = user_state.get_token();
+        use_effect_with((), move |_| {
+            if let Some(token) = token {
+                send.emit(FrontendMessage::Resume(token));
+            }
+        });
+    }
+
+    // The backend forgets the session with the connection, so the worker
+    // resumes it upon reconnecting, before sending anything else.
+    {
+        let websocket = use_websocket();
+        let token = user_state.get_token();
+        use_effect_with(token, move |token| {
+            websocket.replay(token.clone().map(FrontendMessage::Resume));
+        });
+    }
+
+    // A refused resumption is retried once accepted again, for as long as the
+    // session is not replaced in the meantime.
+    {
+        let send = send.clone();
+        let token = user_state.get_token();
+        let resume_retry = use_mut_ref(|| None::<Timeout>);
+        {
+            let resume_retry = resume_retry.clone();
+            use_effect_with(token.clone(), move |_| {
+                *resume_retry.borrow_mut() = None;
+            });
+        }
+        use_effect(move || {
+            if let (Some(retry_after), Some(token)) =
+                (resume_retry_afters.into_iter().last(), token)
+            {
+                log::warn!(
+                    "Session resumption rate limited, retrying in {:?}",
+                    retry_after
+                );
+                *resume_retry.borrow_mut() =
+                    Some(Timeout::new(retry_after.as_millis() as u32, move || {
+                        send.emit(FrontendMessage::Resume(token))
+                    }));
+            }
+        });
+    }
+
+    // The token of the session this socket is logged in with, if any.
+    let socket_token = use_mut_ref(|| None::<String>);
+
+    // When the session changes, either from this tab or from another tab
+    // through the storage synchronization, we tell the backend so that this
+    // socket follows: a new session is resumed unless this socket logged in
+    // with it, and a cleared one is invalidated, stopping the notifications.
+    {
+        let token = user_state.get_token();
+        let previous_token = use_mut_ref(|| token.clone());
+        let socket_token = socket_token.clone();
+        use_effect_with(token, move |token| {
+            let previous_token = previous_token.replace(token.clone());
+            match token {
+                Some(token)
+                    if previous_token.as_ref() != Some(token)
+                        && socket_token.borrow().as_ref() != Some(token) =>
+                {
+                    send.emit(FrontendMessage::Resume(token.clone()));
+                }
+                None if previous_token.is_some() => {
+                    socket_token.take();
+                    send.emit(FrontendMessage::Logout);
+                }
+                _ => {}
+            }
+        });
+    }
+
+    use_effect(move || {
+        for session in sessions {
+            *socket_token.borrow_mut() = session.as_ref().map(|session| session.token.clone());
+            // The session is not logged as is, as its token is a secret.
+            match &session {
+                Some(session) => log::info!("Logged in as {}", session.user.username),
+                None => log::info!("Session expired"),
+            }
+            dispatch.reduce_mut(move |state| {
+                state.session = session;
+            });
+        }
+    });
+
+    html! {}
+}
@@ -0,0 +1,5 @@
+pub mod websocket;
+pub use websocket::{
+    use_backend_messages, use_websocket, use_websocket_output, use_websocket_send,
+    BackendSubscription, WebsocketContext, WebsocketOutput, WebsocketProvider,
+};
@@ -0,0 +1,214 @@
+//! Application-wide access to the websocket worker.
+//!
+//! A single [`WebsocketProvider`] owns the only bridge to the websocket worker
+//! and fans the messages coming from the backend out to every subscriber, so
+//! that rendering many components does not mean opening many bridges.
+
+use crate::worker::{WebsocketWorker, WorkerInput, WorkerOutput};
+use commons::codecs::{Bincode, Deflate};
+use commons::messages::{BackendMessage, FrontendMessage};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use wasm_bindgen::UnwrapThrowExt;
+use yew::prelude::*;
+use yew_agent::prelude::*;
+use yew_agent::worker::WorkerProvider;
+
+/// The websocket worker as used by the application.
+pub type Websocket = WebsocketWorker<FrontendMessage, BackendMessage, Deflate<Bincode>>;
+
+/// Messages of the websocket worker as used by the application.
+pub type WebsocketOutput = WorkerOutput<BackendMessage>;
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: usize,
+    callbacks: HashMap<usize, Callback<WebsocketOutput>>,
+}
+
+#[derive(Clone)]
+/// Handle to the shared websocket, provided by [`WebsocketProvider`].
+pub struct WebsocketContext {
+    bridge: UseWorkerBridgeHandle<Websocket>,
+    subscribers: Rc<RefCell<Subscribers>>,
+}
+
+impl PartialEq for WebsocketContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.bridge == other.bridge && Rc::ptr_eq(&self.subscribers, &other.subscribers)
+    }
+}
+
+impl WebsocketContext {
+    /// Sends the provided message to the backend.
+    pub fn send(&self, message: FrontendMessage) {
+        self.bridge.send(WorkerInput::Send(message));
+    }
+
+    /// Sets the message sent first to the backend upon reconnecting, such as
+    /// to resume the session.
+    pub fn replay(&self, message: Option<FrontendMessage>) {
+        self.bridge.send(WorkerInput::Replay(message));
+    }
+
+    /// Registers a callback receiving every message sent by the backend.
+    ///
+    /// The callback stays registered for as long as the returned subscription
+    /// is kept alive.
+    pub fn subscribe(&self, callback: Callback<BackendMessage>) -> BackendSubscription {
+        self.subscribe_output(Callback::from(move |output| {
+            if let WorkerOutput::Backend(message) = output {
+                callback.emit(message);
+            }
+        }))
+    }
+
+    /// Registers a callback receiving every message of the worker, such as
+    /// the closing of the connection alongside the messages of the backend.
+    pub fn subscribe_output(&self, callback: Callback<WebsocketOutput>) -> BackendSubscription {
+        let mut subscribers = self.subscribers.borrow_mut();
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.callbacks.insert(id, callback);
+        BackendSubscription {
+            id,
+            subscribers: Rc::downgrade(&self.subscribers),
+        }
+    }
+
+    fn dispatch(subscribers: &RefCell<Subscribers>, message: WebsocketOutput) {
+        // We collect the callbacks before emitting, as a callback may well
+        // cause a component to subscribe or unsubscribe.
+        let callbacks: Vec<Callback<WebsocketOutput>> =
+            subscribers.borrow().callbacks.values().cloned().collect();
+        for callback in callbacks {
+            callback.emit(message.clone());
+        }
+    }
+}
+
+/// Subscription to the backend messages, dropped to unsubscribe.
+pub struct BackendSubscription {
+    id: usize,
+    subscribers: Weak<RefCell<Subscribers>>,
+}
+
+impl Drop for BackendSubscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            subscribers.borrow_mut().callbacks.remove(&self.id);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Properties)]
+pub struct WebsocketProviderProps {
+    #[prop_or_default]
+    pub children: Html,
+}
+
+#[function_component]
+#[allow(non_snake_case)]
+/// Spawns the websocket worker and provides the [`WebsocketContext`] to its children.
+pub fn WebsocketProvider(props: &WebsocketProviderProps) -> Html {
+    html! {
+        <WorkerProvider<Websocket> path="web_socket_worker.js">
+            <WebsocketBridge>{props.children.clone()}</WebsocketBridge>
+        </WorkerProvider<Websocket>>
+    }
+}
+
+#[function_component]
+#[allow(non_snake_case)]
+fn WebsocketBridge(props: &WebsocketProviderProps) -> Html {
+    let subscribers = use_mut_ref(Subscribers::default);
+
+    let bridge = {
+        let subscribers = subscribers.clone();
+        use_worker_bridge::<Websocket, _>(move |message: WebsocketOutput| {
+            WebsocketContext::dispatch(&subscribers, message);
+        })
+    };
+
+    let context = WebsocketContext {
+        bridge,
+        subscribers,
+    };
+
+    html! {
+        <ContextProvider<WebsocketContext> {context}>
+            {props.children.clone()}
+        </ContextProvider<WebsocketContext>>
+    }
+}
+
+#[hook]
+/// Returns the [`WebsocketContext`] provided by the closest [`WebsocketProvider`].
+pub fn use_websocket() -> WebsocketContext {
+    use_context::<WebsocketContext>().expect_throw("WebsocketProvider is missing")
+}
+
+#[hook]
+/// Returns a callback sending the provided messages to the backend.
+pub fn use_websocket_send() -> Callback<FrontendMessage> {
+    let websocket = use_websocket();
+    Callback::from(move |message: FrontendMessage| websocket.send(message))
+}
+
+#[hook]
+/// Returns the backend messages accepted by the provided filter since the
+/// previous render, in the order they were received.
+///
+/// The filter maps the backend messages the component is interested in into
+/// the values to be handled, and returns `None` for all other messages. The
+/// component is re-rendered upon every accepted message, which is returned by
+/// a single render, so that the messages of a batch are all handled.
+pub fn use_backend_messages<T, F>(filter: F) -> Vec<T>
+where
+    T: 'static,
+    F: Fn(BackendMessage) -> Option<T> + 'static,
+{
+    use_websocket_output(move |output| match output {
+        WorkerOutput::Backend(message) => filter(message),
+        _ => None,
+    })
+}
+
+#[hook]
+/// Returns the messages of the worker accepted by the provided filter since
+/// the previous render, as [`use_backend_messages`] does with the messages of
+/// the backend.
+pub fn use_websocket_output<T, F>(filter: F) -> Vec<T>
+where
+    T: 'static,
+    F: Fn(WebsocketOutput) -> Option<T> + 'static,
+{
+    let websocket = use_websocket();
+    let update = use_force_update();
+    let received = use_mut_ref(Vec::new);
+
+    // The subscription outlives the render, so that it filters the messages
+    // with the closure of the latest render rather than of the first one.
+    let latest_filter = use_mut_ref(|| None::<F>);
+    *latest_filter.borrow_mut() = Some(filter);
+
+    {
+        let received = received.clone();
+        use_effect_with(websocket, move |websocket| {
+            let subscription = websocket.subscribe_output(Callback::from(move |message| {
+                let value = latest_filter
+                    .borrow()
+                    .as_ref()
+                    .and_then(|filter| filter(message));
+                if let Some(value) = value {
+                    received.borrow_mut().push(value);
+                    update.force_update();
+                }
+            }));
+            move || drop(subscription)
+        });
+    }
+
+    received.take()
+}
@@ -1,7 +1,8 @@
 //! This is the main file for the frontend. It will render the App component.
 pub mod components;
-pub mod worker;
-pub mod utils;
+pub mod contexts;
 pub mod pages;
 pub mod router;
-pub mod stores;
\ No newline at end of file
+pub mod stores;
+pub mod utils;
+pub mod worker;
@@ -1,19 +1,20 @@
 //! Login page of the application.
 
+use crate::components::LoginForm;
 use crate::router::AppRoute;
 use crate::stores::UserState;
 use yew::prelude::*;
 use yew_router::prelude::*;
 use yewdux::prelude::*;
-use crate::components::LoginForm;
 
 #[function_component(Login)]
 pub fn login() -> Html {
-    let navigator = use_navigator().unwrap();
     let (user, _dispatch) = use_store::<UserState>();
 
     if user.is_logged_in() {
-        navigator.push(&AppRoute::Comments);
+        return html! {
+            <Redirect<AppRoute> to={AppRoute::Comments} />
+        };
     }
 
     html! {
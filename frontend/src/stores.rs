@@ -1,2 +1,2 @@
 pub mod user;
-pub use user::UserState;
\ No newline at end of file
+pub use user::UserState;
@@ -1,24 +1,33 @@
-use commons::users::User;
+use commons::users::{Session, User};
 use serde::{Deserialize, Serialize};
 use yewdux::prelude::*;
 
 #[derive(Default, PartialEq, Serialize, Deserialize, Store, Clone, Debug)]
-/// The following macro will make sure that the store is saved across sessions.
-// #[store(storage = "session", storage_tab_sync)]
+/// The following macro will make sure that the store is saved across sessions,
+/// and kept in sync across the open tabs.
+#[store(storage = "local", storage_tab_sync)]
 pub struct UserState {
-    pub user: Option<User>,
+    pub session: Option<Session>,
 }
 
 impl UserState {
     pub fn is_logged_in(&self) -> bool {
-        self.user.is_some()
+        self.session.is_some()
     }
 
     pub fn is_not_logged_in(&self) -> bool {
-        self.user.is_none()
+        self.session.is_none()
     }
 
     pub fn get_user(&self) -> Option<User> {
-        self.user.clone()
+        self.session.as_ref().map(|session| session.user.clone())
+    }
+
+    pub fn logout(&mut self) {
+        self.session = None;
+    }
+
+    pub fn get_token(&self) -> Option<String> {
+        self.session.as_ref().map(|session| session.token.clone())
     }
 }
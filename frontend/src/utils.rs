@@ -1,4 +1,4 @@
-use wasm_bindgen::UnwrapThrowExt;
+use wasm_bindgen::{JsCast, UnwrapThrowExt};
 
 pub fn is_https() -> bool {
     let window = web_sys::window().unwrap_throw();
@@ -16,4 +16,24 @@ pub fn host() -> String {
     let window = web_sys::window().unwrap_throw();
     let location = window.location();
     location.hostname().unwrap_throw()
-}
\ No newline at end of file
+}
+
+/// Returns the URL of the websocket endpoint, served by the same origin as the worker.
+///
+/// This function is meant to be called from within a web worker, where no
+/// window is available. In development, trunk proxies the endpoint to the backend.
+/// The backend resumes after the event `last_event_id`, when provided.
+pub fn websocket_url(last_event_id: Option<i64>) -> String {
+    let location = js_sys::global()
+        .unchecked_into::<web_sys::WorkerGlobalScope>()
+        .location();
+    let protocol = if location.protocol().starts_with("https") {
+        "wss"
+    } else {
+        "ws"
+    };
+    match last_event_id {
+        Some(id) => format!("{}://{}/ws?last_event_id={}", protocol, location.host(), id),
+        None => format!("{}://{}/ws", protocol, location.host()),
+    }
+}
@@ -1,6 +1,11 @@
+use commons::codecs::Codec;
+use commons::messages::{protocol, BackendMessage, CloseReason};
 use futures::{SinkExt, StreamExt};
 use gloo::timers::callback::Timeout;
 use gloo_net::websocket::futures::WebSocket;
+use gloo_net::websocket::{Message, WebSocketError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fmt::Debug;
 use wasm_bindgen::UnwrapThrowExt;
@@ -8,41 +13,106 @@ use yew::platform::spawn_local;
 use yew_agent::worker::HandlerId;
 use yew_agent::worker::Worker;
 
-const NOMINAL_CLOSURE_CODE: u16 = 1000;
+const NOMINAL_CLOSURE_CODE: u16 = CloseReason::NORMAL;
+
+/// Maximum delay in milliseconds added to the reconnection delay, so that the
+/// clients do not all reconnect at the same time.
+const RECONNECTION_JITTER: f64 = 1000.0;
+
+/// Delay in milliseconds before reconnecting, for each failed attempt since
+/// the connection was last open.
+const RECONNECTION_BACKOFF: u32 = 1000;
+
+/// Maximum delay in milliseconds before reconnecting, unless hinted otherwise
+/// by the backend.
+const MAX_RECONNECTION_DELAY: u32 = 30_000;
+
+/// Messages of the backend telling which event the client can resume after.
+pub trait Resumable {
+    /// Returns the id of the event the previous messages were sent for, if any.
+    fn last_event_id(&self) -> Option<i64>;
+}
+
+impl Resumable for BackendMessage {
+    fn last_event_id(&self) -> Option<i64> {
+        match self {
+            BackendMessage::LastEventId(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct WebsocketWorker<FM, BM> {
+pub struct WebsocketWorker<FM, BM, C> {
     subscribers: HashSet<HandlerId>,
     sender: Option<futures::channel::mpsc::Sender<FM>>,
+    /// Messages received while no connection was available, sent upon connecting.
+    pending: Vec<FM>,
     reconnection_attempt: u32,
-    _phantom: std::marker::PhantomData<BM>,
+    /// Id of the last event received, to resume after when reconnecting.
+    last_event_id: Option<i64>,
+    /// Message sent first upon reconnecting.
+    replay: Option<FM>,
+    /// Whether a connection was made before, the next ones being reconnections.
+    connected_before: bool,
+    _phantom: std::marker::PhantomData<(BM, C)>,
+}
+
+/// Messages of the subscribers to the worker.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WorkerInput<FM> {
+    /// Message to send to the backend, once connected.
+    Send(FM),
+    /// Message to send first upon reconnecting, such as to resume the session
+    /// the backend forgot with the previous connection, or `None` to stop
+    /// sending it.
+    Replay(Option<FM>),
+}
+
+/// Messages of the worker to its subscribers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WorkerOutput<BM> {
+    /// Message sent by the backend.
+    Backend(BM),
+    /// The connection was closed by the backend, possibly hinting when to
+    /// reconnect.
+    Closed(CloseReason),
 }
 
 #[derive(Clone, Debug)]
 pub enum InternalMessage<BM> {
     Backend(BM),
     Disconnect(Option<u16>),
+    /// The connection is open.
+    Opened,
+    /// The backend closed the connection, possibly hinting when to reconnect.
+    Closed(CloseReason),
+    /// The connection failed without being closed by the backend.
+    Lost,
     Reconnect,
 }
 
-impl<FM, BM> WebsocketWorker<FM, BM>
+impl<FM, BM, C> WebsocketWorker<FM, BM, C>
 where
-    FM: Into<gloo_net::websocket::Message> + Clone + 'static + Debug,
-    BM: From<gloo_net::websocket::Message> + Clone + 'static + Debug,
+    FM: Serialize + Clone + 'static + Debug,
+    BM: Serialize + DeserializeOwned + Resumable + Clone + 'static + Debug,
+    Vec<BM>: From<BM>,
+    C: Codec,
 {
     fn connect(
         scope: &yew_agent::prelude::WorkerScope<Self>,
+        last_event_id: Option<i64>,
     ) -> Result<futures::channel::mpsc::Sender<FM>, String> {
-        let websocket = WebSocket::open("ws://localhost:8080/ws").map_err(|err| {
-            format!(
-                "Error opening websocket connection to ws://localhost:8080/ws: {:?}",
-                err
-            )
-        })?;
+        let url = crate::utils::websocket_url(last_event_id);
+        // The websocket is kept to tell whether it opened, as gloo does not.
+        let raw = web_sys::WebSocket::new_with_str(&url, &protocol::<C>())
+            .map_err(|err| format!("Error opening websocket connection to {}: {:?}", url, err))?;
+        let websocket = WebSocket::try_from(raw.clone())
+            .map_err(|err| format!("Error setting up websocket connection: {:?}", err))?;
 
         match websocket.state() {
-            gloo_net::websocket::State::Open => {},
-            gloo_net::websocket::State::Connecting => {},
+            gloo_net::websocket::State::Open => {}
+            gloo_net::websocket::State::Connecting => {}
             _ => {
                 return Err("Websocket connection is not open".to_string());
             }
@@ -52,9 +122,29 @@ where
 
         let (sender, mut receiver) = futures::channel::mpsc::channel::<FM>(1000);
 
+        let writer_scope = scope.clone();
         spawn_local(async move {
+            // The websocket is ready to be written to once open, or failed.
+            let ready = futures::future::poll_fn(|cx| write.poll_ready_unpin(cx)).await;
+            if ready.is_ok() && raw.ready_state() == web_sys::WebSocket::OPEN {
+                writer_scope.send_message(InternalMessage::Opened);
+            }
             while let Some(frontend_message) = receiver.next().await {
-                if write.send(frontend_message.into()).await.is_err() {
+                let message = match C::encode(&frontend_message) {
+                    Ok(bytes) if C::TEXT => match String::from_utf8(bytes) {
+                        Ok(text) => Message::Text(text),
+                        Err(err) => {
+                            log::error!("Error encoding {:?}: {}", frontend_message, err);
+                            continue;
+                        }
+                    },
+                    Ok(bytes) => Message::Bytes(bytes),
+                    Err(err) => {
+                        log::error!("Error encoding {:?}: {}", frontend_message, err);
+                        continue;
+                    }
+                };
+                if write.send(message).await.is_err() {
                     log::error!("Error sending to websocket");
                     break;
                 }
@@ -67,7 +157,26 @@ where
                 while let Some(backend_message) = read.next().await {
                     match backend_message {
                         Ok(message) => {
-                            scope.send_message(InternalMessage::Backend(message.into()));
+                            let bytes = match &message {
+                                Message::Bytes(bytes) => bytes.as_slice(),
+                                Message::Text(text) => text.as_bytes(),
+                            };
+                            // The backend is trusted not to send oversized messages.
+                            match C::decode(bytes, u64::MAX) {
+                                Ok(message) => {
+                                    // Batches are unpacked for the subscribers.
+                                    for message in Vec::from(message) {
+                                        scope.send_message(InternalMessage::Backend(message));
+                                    }
+                                }
+                                Err(err) => {
+                                    log::error!("Error decoding websocket message: {}", err);
+                                }
+                            }
+                        }
+                        Err(WebSocketError::ConnectionClose(event)) => {
+                            scope.send_message(InternalMessage::Closed(event.into()));
+                            return;
                         }
                         Err(err) => {
                             log::error!("Error reading from websocket: {:?}", err);
@@ -75,22 +184,46 @@ where
                         }
                     }
                 }
-                scope.send_message(InternalMessage::Reconnect);
+                scope.send_message(InternalMessage::Lost);
             });
         }
 
         Ok(sender)
     }
+
+    /// Reconnects after a delay growing with the failed attempts, waiting at
+    /// least as long as hinted by the backend.
+    fn reconnect_later(
+        &mut self,
+        scope: &yew_agent::prelude::WorkerScope<Self>,
+        retry_after: Option<std::time::Duration>,
+    ) {
+        self.reconnection_attempt += 1;
+        let backoff = self
+            .reconnection_attempt
+            .saturating_mul(RECONNECTION_BACKOFF)
+            .min(MAX_RECONNECTION_DELAY);
+        let hinted = retry_after.map_or(0, |retry_after| retry_after.as_millis() as u32);
+        let delay = backoff.max(hinted) as f64 + js_sys::Math::random() * RECONNECTION_JITTER;
+        log::debug!("Reconnecting to websocket in {} milliseconds", delay as u32);
+        let scope = scope.clone();
+        Timeout::new(delay as u32, move || {
+            scope.send_message(InternalMessage::Reconnect);
+        })
+        .forget();
+    }
 }
 
-impl<FM, BM> Worker for WebsocketWorker<FM, BM>
+impl<FM, BM, C> Worker for WebsocketWorker<FM, BM, C>
 where
-    FM: Into<gloo_net::websocket::Message> + Clone + 'static + Debug,
-    BM: From<gloo_net::websocket::Message> + Clone + 'static + Debug,
+    FM: Serialize + Clone + 'static + Debug,
+    BM: Serialize + DeserializeOwned + Resumable + Clone + 'static + Debug,
+    Vec<BM>: From<BM>,
+    C: Codec,
 {
     type Message = InternalMessage<BM>;
-    type Input = FM;
-    type Output = BM;
+    type Input = WorkerInput<FM>;
+    type Output = WorkerOutput<BM>;
 
     fn create(scope: &yew_agent::prelude::WorkerScope<Self>) -> Self {
         let scope = scope.clone();
@@ -101,7 +234,11 @@ where
         Self {
             subscribers: HashSet::new(),
             sender: None,
+            pending: Vec::new(),
             reconnection_attempt: 0,
+            last_event_id: None,
+            replay: None,
+            connected_before: false,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -114,8 +251,14 @@ where
         match internal_message {
             InternalMessage::Backend(backend_message) => {
                 log::debug!("Received message from websocket: {:?}", backend_message);
+                self.reconnection_attempt = 0;
+                // The events are received in the order of the backend, so
+                // the last one is the one to resume after.
+                if let Some(last_event_id) = backend_message.last_event_id() {
+                    self.last_event_id = Some(last_event_id);
+                }
                 for sub in &self.subscribers {
-                    scope.respond(*sub, backend_message.clone());
+                    scope.respond(*sub, WorkerOutput::Backend(backend_message.clone()));
                 }
             }
             InternalMessage::Disconnect(closure_code) => {
@@ -125,24 +268,47 @@ where
                     });
                 }
             }
+            InternalMessage::Closed(reason) => {
+                log::debug!("Websocket closed by the backend: {:?}", reason);
+                // The messages sent until reconnecting are kept as pending.
+                self.sender = None;
+                for sub in &self.subscribers {
+                    scope.respond(*sub, WorkerOutput::Closed(reason.clone()));
+                }
+                if reason.code() == CloseReason::INCOMPATIBLE_PROTOCOL {
+                    // Reconnecting cannot help until the page is reloaded.
+                    return;
+                }
+                self.reconnect_later(scope, reason.retry_after());
+            }
+            InternalMessage::Opened => {
+                log::debug!("Connected to websocket");
+                self.reconnection_attempt = 0;
+            }
+            InternalMessage::Lost => {
+                log::debug!("Websocket connection lost");
+                self.sender = None;
+                self.reconnect_later(scope, None);
+            }
             InternalMessage::Reconnect => {
                 if let Some(mut sender) = self.sender.take() {
                     spawn_local(async move {
                         sender.close().await.unwrap_throw();
                     });
                 }
-                if let Ok(sender) = Self::connect(scope) {
-                    log::debug!("Reconnected to websocket");
-                    self.reconnection_attempt = 0;
+                if let Ok(mut sender) = Self::connect(scope, self.last_event_id) {
+                    // The attempts are only reset once the connection is open.
+                    let replayed = self.replay.clone().filter(|_| self.connected_before);
+                    self.connected_before = true;
+                    for frontend_message in replayed.into_iter().chain(self.pending.drain(..)) {
+                        if let Err(err) = sender.try_send(frontend_message) {
+                            log::error!("Error sending message to websocket: {:?}", err);
+                        }
+                    }
                     self.sender = Some(sender);
                 } else {
-                    log::debug!("Failed to reconnect to websocket, attempting again in {} seconds", self.reconnection_attempt);
-                    self.reconnection_attempt += 1;
-                    let scope = scope.clone();
-                    Timeout::new(self.reconnection_attempt * 1000, move || {
-                        scope.send_message(InternalMessage::Reconnect);
-                    })
-                    .forget();
+                    log::debug!("Failed to reconnect to websocket");
+                    self.reconnect_later(scope, None);
                 }
             }
         }
@@ -172,9 +338,16 @@ where
     fn received(
         &mut self,
         _scope: &yew_agent::prelude::WorkerScope<Self>,
-        frontend_message: Self::Input,
+        input: Self::Input,
         _id: HandlerId,
     ) {
+        let frontend_message = match input {
+            WorkerInput::Send(frontend_message) => frontend_message,
+            WorkerInput::Replay(replay) => {
+                self.replay = replay;
+                return;
+            }
+        };
         if let Some(sender) = &mut self.sender {
             match sender.try_send(frontend_message) {
                 Ok(()) => {}
@@ -182,6 +355,8 @@ where
                     log::error!("Error sending message to websocket: {:?}", err);
                 }
             }
+        } else {
+            self.pending.push(frontend_message);
         }
     }
 }